@@ -1,4 +1,86 @@
+use schemars::JsonSchema;
+use serde::Deserialize;
+use std::ffi::OsString;
+use std::fmt;
 use std::string::String;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// Whether the console output is colorized
+static COLORS_ENABLED: AtomicBool = AtomicBool::new(true);
+/// Whether the messages echoed by the shell commands are colorized
+static SHELL_COLORS_ENABLED: AtomicBool = AtomicBool::new(true);
+
+/// When to use colors in the output
+#[derive(Debug, Clone, Copy, PartialEq, Default, Deserialize, JsonSchema, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum ColorMode {
+    #[default]
+    Auto,
+    Always,
+    Never,
+}
+
+impl fmt::Display for ColorMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ColorMode::Auto => write!(f, "auto"),
+            ColorMode::Always => write!(f, "always"),
+            ColorMode::Never => write!(f, "never"),
+        }
+    }
+}
+
+/// Check if colors are used: `auto` disables them if `NO_COLOR` is set, forces them if
+/// `CLICOLOR_FORCE` is set, and otherwise uses them only when writing to a terminal
+pub fn use_colors(mode: ColorMode, is_terminal: bool) -> bool {
+    use_colors_with(mode, is_terminal, |name| std::env::var_os(name))
+}
+
+fn use_colors_with(
+    mode: ColorMode,
+    is_terminal: bool,
+    var: impl Fn(&str) -> Option<OsString>,
+) -> bool {
+    let is_set = |name| var(name).is_some_and(|value| !value.is_empty() && value != "0");
+    match mode {
+        ColorMode::Always => true,
+        ColorMode::Never => false,
+        ColorMode::Auto if var("NO_COLOR").is_some_and(|value| !value.is_empty()) => false,
+        ColorMode::Auto if is_set("CLICOLOR_FORCE") => true,
+        ColorMode::Auto => is_terminal,
+    }
+}
+
+/// Enable or disable the colors for all the console output, including the shell printers
+pub fn set_colors_enabled(enabled: bool) {
+    COLORS_ENABLED.store(enabled, Ordering::Relaxed);
+    console::set_colors_enabled(enabled);
+    console::set_colors_enabled_stderr(enabled);
+}
+
+/// Enable or disable the colors of the messages echoed by the shell commands. They are part of
+/// the standard output, evaluated by the shell when setting up an environment.
+pub fn set_shell_colors_enabled(enabled: bool) {
+    SHELL_COLORS_ENABLED.store(enabled, Ordering::Relaxed);
+}
+
+/// Colorize a message echoed by the shell commands, if colors are enabled for them
+pub fn for_shell(text: &str, colorize: impl Fn(&str) -> String) -> String {
+    if SHELL_COLORS_ENABLED.load(Ordering::Relaxed) {
+        colorize(text)
+    } else {
+        text.to_string()
+    }
+}
+
+/// Surround the text with the ANSI escape code, if colors are enabled
+fn paint(code: &str, text: String) -> String {
+    if COLORS_ENABLED.load(Ordering::Relaxed) {
+        format!("\x1b[{}m{}\x1b[0m", code, text)
+    } else {
+        text
+    }
+}
 
 /// Trait to colorize strings for console output
 pub trait Colorize {
@@ -9,21 +91,17 @@ pub trait Colorize {
 }
 
 impl Colorize for String {
-    fn warning(mut self) -> String {
-        self = "\x1b[1;33m".to_string() + &self + "\x1b[0m";
-        self
+    fn warning(self) -> String {
+        paint("1;33", self)
     }
-    fn error(mut self) -> String {
-        self = "\x1b[1;31m".to_string() + &self + "\x1b[0m";
-        self
+    fn error(self) -> String {
+        paint("1;31", self)
     }
-    fn info(mut self) -> String {
-        self = "\x1b[0;34m".to_string() + &self + "\x1b[0m";
-        self
+    fn info(self) -> String {
+        paint("0;34", self)
     }
-    fn success(mut self) -> String {
-        self = "\x1b[1;32m".to_string() + &self + "\x1b[0m";
-        self
+    fn success(self) -> String {
+        paint("1;32", self)
     }
 }
 
@@ -45,3 +123,44 @@ impl Colorize for &str {
         result.success()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_use_colors() {
+        type Vars = &'static [(&'static str, &'static str)];
+        let cases: [(ColorMode, bool, Vars, bool); 9] = [
+            (ColorMode::Auto, true, &[], true),
+            (ColorMode::Auto, false, &[], false),
+            (ColorMode::Auto, true, &[("NO_COLOR", "1")], false),
+            (ColorMode::Auto, true, &[("NO_COLOR", "")], true),
+            (ColorMode::Auto, false, &[("CLICOLOR_FORCE", "1")], true),
+            (ColorMode::Auto, false, &[("CLICOLOR_FORCE", "0")], false),
+            (
+                ColorMode::Auto,
+                true,
+                &[("NO_COLOR", "1"), ("CLICOLOR_FORCE", "1")],
+                false,
+            ),
+            (ColorMode::Always, false, &[("NO_COLOR", "1")], true),
+            (ColorMode::Never, true, &[("CLICOLOR_FORCE", "1")], false),
+        ];
+        for (mode, is_terminal, vars, expected) in cases {
+            let var = |name: &str| {
+                vars.iter()
+                    .find(|(key, _)| *key == name)
+                    .map(|(_, value)| OsString::from(value))
+            };
+            assert_eq!(
+                use_colors_with(mode, is_terminal, var),
+                expected,
+                "{:?} {} {:?}",
+                mode,
+                is_terminal,
+                vars
+            );
+        }
+    }
+}
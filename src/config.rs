@@ -1,51 +1,318 @@
+use indexmap::IndexMap;
 use regex::Regex;
-use semver::Version;
-use serde::Deserialize;
+use schemars::JsonSchema;
+use semver::VersionReq;
+use serde::de::{self, Visitor};
+use serde::{Deserialize, Deserializer, Serialize};
 use std::collections::HashMap;
+use std::fmt;
 use std::fs;
-use std::io::BufReader;
 use std::path::{Path, PathBuf};
 use std::sync::LazyLock;
+use std::time::SystemTime;
 
 use crate::Shell;
-use crate::context::Context;
-use crate::settings::Settings;
-use crate::shell::ShellPrinter;
+use crate::context::{Context, detect_os};
+use crate::error::{Error, SourceFile};
+use crate::requirements::Requirement;
+use crate::settings::{FileSettings, Settings};
+use crate::shell::{ENV_VAR_REGEX, ShellPrinter};
+use crate::version::{Prefer, VersionSort, parse_lenient};
+
+mod doctor;
+mod edit;
+mod format;
+mod migrate;
+pub use doctor::Severity;
+pub use edit::ConfigEdit;
+pub use format::{CONFIG_FILE_NAMES, ConfigFormat};
+pub use migrate::Migration;
+
+/// Environment variable listing the environments already used in the shell
+pub const ACTIVE_ENVS_VAR: &str = "USE_ENVS";
+/// Key of the settings section in the configuration file, not an environment
+pub const SETTINGS_KEY: &str = "settings";
+
+static PLACEHOLDER_REGEX: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"\{[A-Za-z_][A-Za-z0-9_]*\}").unwrap());
+
+/// `{version}` in a title, with the brackets and the space before it, removed for the
+/// environments without a version
+static EMPTY_VERSION_REGEX: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"\s*(\(\{version\}\)|\[\{version\}\]|\{version\})").unwrap());
+
+/// Deserialize a regex, checking its syntax. Named placeholders (e.g. `{arch}`) are allowed.
+fn deserialize_regex<'de, D: Deserializer<'de>>(deserializer: D) -> Result<String, D::Error> {
+    struct RegexVisitor;
+
+    impl Visitor<'_> for RegexVisitor {
+        type Value = String;
+
+        fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            f.write_str("a regular expression")
+        }
+
+        fn visit_str<E: de::Error>(self, value: &str) -> Result<String, E> {
+            let checked = PLACEHOLDER_REGEX.replace_all(value, "x");
+            match Regex::new(&checked) {
+                Ok(_) => Ok(value.to_string()),
+                // Only keep the last line of the error, the others show the regex
+                Err(e) => Err(E::custom(format!(
+                    "invalid regex {}: {}",
+                    value,
+                    e.to_string().lines().last().unwrap_or_default()
+                ))),
+            }
+        }
+    }
+
+    deserializer.deserialize_str(RegexVisitor)
+}
+
+fn deserialize_optional_regex<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<String>, D::Error> {
+    deserialize_regex(deserializer).map(Some)
+}
 
-static ENV_VAR_REGEX: LazyLock<Regex> =
-    LazyLock::new(|| Regex::new(r"\$\{([A-Za-z_][A-Za-z0-9_]*)\}").unwrap());
+fn is_zero(value: &i32) -> bool {
+    *value == 0
+}
 
 /// Struct to hold the pattern of the environment
-#[derive(Debug, Deserialize, Clone, PartialEq)]
+#[derive(Debug, Deserialize, Serialize, JsonSchema, Clone, PartialEq, Default)]
 struct Pattern {
+    /// Path to look at
     path: String,
+    /// Regex to match files/dirs in the path, captures replace the `{}` placeholders
+    #[serde(deserialize_with = "deserialize_regex")]
     regex: String,
+    /// How to order the matching versions
+    #[serde(default)]
+    version_sort: VersionSort,
+    /// Read the version from a file inside each match, instead of the first capture
+    version_from: Option<VersionFrom>,
+    /// Version selected when using a partial name: newest, oldest or a version constraint
+    #[serde(default)]
+    prefer: Prefer,
+}
+
+/// Struct to hold where to read the version of a pattern environment
+#[derive(Debug, Deserialize, Serialize, JsonSchema, Clone, PartialEq)]
+struct VersionFrom {
+    /// File to read, relative to the matched file or directory
+    file: String,
+    /// Regex used to extract the version from the file, the first capture is the version
+    #[serde(default, deserialize_with = "deserialize_optional_regex")]
+    regex: Option<String>,
+}
+
+impl VersionFrom {
+    /// Read the version from the file inside the given path
+    fn read(&self, path: &Path, re: Option<&Regex>) -> Option<String> {
+        let content = fs::read_to_string(path.join(&self.file)).ok()?;
+        let version = match re {
+            Some(re) => re.captures(&content)?.get(1)?.as_str(),
+            None => content.lines().next()?,
+        };
+        let version = version.trim();
+        (!version.is_empty()).then(|| version.to_string())
+    }
+}
+
+/// Struct to hold an environment used by another environment
+#[derive(Debug, Deserialize, Serialize, JsonSchema, Clone, PartialEq, Default)]
+#[serde(try_from = "DependencyEntry", into = "DependencyEntry")]
+struct Dependency {
+    name: String,
+    /// Skip the dependency instead of failing if it can't be found
+    optional: bool,
+    /// Only use the dependency in the given context (e.g. `windows`)
+    context: Option<String>,
+    /// Version constraint on the dependency (e.g. `>=6.5`)
+    #[schemars(with = "Option<String>")]
+    version: Option<VersionReq>,
+}
+
+/// A dependency is either a name or a detailed entry
+#[derive(Deserialize, Serialize, JsonSchema)]
+#[serde(untagged)]
+enum DependencyEntry {
+    Name(String),
+    Detailed {
+        name: String,
+        #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+        optional: bool,
+        #[serde(rename = "for", skip_serializing_if = "Option::is_none")]
+        context: Option<String>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        version: Option<String>,
+    },
+}
+
+impl From<Dependency> for DependencyEntry {
+    fn from(dependency: Dependency) -> Self {
+        match dependency {
+            Dependency {
+                name,
+                optional: false,
+                context: None,
+                version: None,
+            } => DependencyEntry::Name(name),
+            Dependency {
+                name,
+                optional,
+                context,
+                version,
+            } => DependencyEntry::Detailed {
+                name,
+                optional,
+                context,
+                version: version.map(|v| v.to_string()),
+            },
+        }
+    }
+}
+
+impl TryFrom<DependencyEntry> for Dependency {
+    type Error = String;
+
+    fn try_from(entry: DependencyEntry) -> Result<Self, Self::Error> {
+        match entry {
+            DependencyEntry::Name(name) => Ok(Self {
+                name,
+                ..Default::default()
+            }),
+            DependencyEntry::Detailed {
+                name,
+                optional,
+                context,
+                version,
+            } => {
+                let version = version
+                    .map(|v| {
+                        VersionReq::parse(&v).map_err(|e| {
+                            format!("invalid version constraint {} for {}: {}", v, name, e)
+                        })
+                    })
+                    .transpose()?;
+                Ok(Self {
+                    name,
+                    optional,
+                    context,
+                    version,
+                })
+            }
+        }
+    }
+}
+
+impl From<&str> for Dependency {
+    fn from(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            ..Default::default()
+        }
+    }
+}
+
+/// Paths added to a list variable, like `LD_LIBRARY_PATH`
+#[derive(Debug, Deserialize, Serialize, JsonSchema, Clone, PartialEq, Default)]
+#[serde(from = "PathListEntry", into = "PathListEntry")]
+struct PathList {
+    /// Paths added at the beginning of the variable
+    prepend: Vec<String>,
+    /// Paths added at the end of the variable
+    append: Vec<String>,
+}
+
+/// A path list is either the paths to prepend, or the paths for each mode
+#[derive(Deserialize, Serialize, JsonSchema)]
+#[serde(untagged)]
+enum PathListEntry {
+    Prepend(Vec<String>),
+    Modes(PathModes),
+}
+
+/// Paths added at the beginning or at the end of the variable
+#[derive(Deserialize, Serialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+struct PathModes {
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    prepend: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    append: Vec<String>,
+}
+
+impl From<PathList> for PathListEntry {
+    fn from(list: PathList) -> Self {
+        if list.append.is_empty() {
+            PathListEntry::Prepend(list.prepend)
+        } else {
+            PathListEntry::Modes(PathModes {
+                prepend: list.prepend,
+                append: list.append,
+            })
+        }
+    }
+}
+
+impl From<PathListEntry> for PathList {
+    fn from(entry: PathListEntry) -> Self {
+        match entry {
+            PathListEntry::Prepend(prepend) => Self {
+                prepend,
+                append: Vec::new(),
+            },
+            PathListEntry::Modes(PathModes { prepend, append }) => Self { prepend, append },
+        }
+    }
 }
 
 /// Common struct for global and shell-specific configuration
-#[derive(Debug, Deserialize, Clone, PartialEq, Default)]
+#[derive(Debug, Deserialize, Serialize, JsonSchema, Clone, PartialEq, Default)]
 struct CommonProperties {
+    /// String displayed when setting the environment
     display: Option<String>,
+    /// Template of the terminal title, using {name}, {display} and {version}
+    title: Option<String>,
+    /// Change the terminal title when setting up this environment
+    update_title: Option<bool>,
+    /// Raw lines to call as a script
     script: Option<String>,
-    set: Option<HashMap<String, String>>,
-    append: Option<HashMap<String, String>>,
-    prepend: Option<HashMap<String, String>>,
+    /// Environment variables to initialize
+    set: Option<IndexMap<String, String>>,
+    /// Values appended to environment variables
+    append: Option<IndexMap<String, String>>,
+    /// Values prepended to environment variables
+    prepend: Option<IndexMap<String, String>>,
+    /// Separators of the list variables, replacing the one of the platform (`;` on Windows,
+    /// `:` otherwise)
+    separator: Option<IndexMap<String, String>>,
+    /// Paths added to the PATH environment variable
     path: Option<Vec<String>>,
+    /// Paths added to other list variables, prepended unless given with `append`
+    paths: Option<IndexMap<String, PathList>>,
+    /// Environments set up before this one
     #[serde(rename = "use")]
-    reuse: Option<Vec<String>>,
+    reuse: Option<Vec<Dependency>>,
+    /// Directory to go to at the end of the setup
     go: Option<String>,
-    alias: Option<HashMap<String, String>>,
+    /// Aliases for this environment
+    alias: Option<IndexMap<String, String>>,
 }
 
 impl CommonProperties {
     /// Merges another CommonProperties into self.
     fn merge(&mut self, other: Self) {
         self.display = other.display.or(self.display.take());
+        self.title = other.title.or(self.title.take());
+        self.update_title = other.update_title.or(self.update_title.take());
         self.script = other.script.or(self.script.take());
         self.go = other.go.or(self.go.take());
 
-        let merge_map = |target: &mut Option<HashMap<String, String>>,
-                         source: Option<HashMap<String, String>>| {
+        let merge_map = |target: &mut Option<IndexMap<String, String>>,
+                         source: Option<IndexMap<String, String>>| {
             if let Some(source_map) = source {
                 target
                     .get_or_insert_with(Default::default)
@@ -57,42 +324,86 @@ impl CommonProperties {
         merge_map(&mut self.append, other.append);
         merge_map(&mut self.prepend, other.prepend);
         merge_map(&mut self.alias, other.alias);
+        merge_map(&mut self.separator, other.separator);
 
-        let merge_vec = |target: &mut Option<Vec<String>>, source: Option<Vec<String>>| {
+        fn merge_vec<T>(target: &mut Option<Vec<T>>, source: Option<Vec<T>>) {
             if let Some(source_vec) = source {
                 target
                     .get_or_insert_with(Default::default)
                     .extend(source_vec);
             }
-        };
+        }
 
         merge_vec(&mut self.path, other.path);
+
+        if let Some(source_paths) = other.paths {
+            let target_paths = self.paths.get_or_insert_with(Default::default);
+            for (key, source) in source_paths {
+                let target = target_paths.entry(key).or_default();
+                target.prepend.extend(source.prepend);
+                target.append.extend(source.append);
+            }
+        }
         merge_vec(&mut self.reuse, other.reuse);
     }
 }
 
 /// Struct to hold the environment configuration
-#[derive(Debug, Deserialize, Clone, PartialEq)]
+#[derive(Debug, Deserialize, Serialize, JsonSchema, Clone, PartialEq, Default)]
 pub struct Environment {
     // The name is read from the file at a different level, see method read_config_file
     #[serde(skip)]
     name: String,
 
+    /// Only define the environment in a given context (e.g. `windows,powershell`)
     #[serde(rename = "for")]
     context: Option<String>,
+    /// Create multiple environments from the files/dirs matching a pattern
     pattern: Option<Pattern>,
+    /// Create one environment for each combination of values, each axis being a placeholder
+    matrix: Option<IndexMap<String, Vec<String>>>,
+    /// Capabilities provided by this environment, usable as a dependency name
+    provides: Option<Vec<String>>,
+    /// Priority of the environment when selecting a provider for a capability
+    #[serde(default, skip_serializing_if = "is_zero")]
+    priority: i32,
+    /// Environments that can't be used at the same time, `*` and `?` wildcards are supported
+    conflicts: Option<Vec<String>>,
+    /// Commands, files or environment variables needed to use this environment
+    requires: Option<Vec<Requirement>>,
+    /// Name of the environment whose properties are inherited
+    extends: Option<String>,
+    /// Abstract environments are only used as templates for other environments
+    #[serde(
+        default,
+        rename = "abstract",
+        skip_serializing_if = "std::ops::Not::not"
+    )]
+    is_abstract: bool,
+    /// Environment selected first when a partial name matches several environments
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    default: bool,
 
     #[serde(flatten)]
     global: CommonProperties,
+    /// Values specific to the cmd shell
     for_cmd: Option<CommonProperties>,
+    /// Values specific to the powershell shell
     #[serde(alias = "for_pwsh")]
     for_powershell: Option<CommonProperties>,
 
-    // Internal properties
-    #[serde(skip)]
+    // Internal properties, the version is shown by `use config show`
+    #[serde(skip_deserializing)]
+    #[schemars(skip)]
     version: Option<String>,
     #[serde(skip)]
     original_name: Option<String>,
+    #[serde(skip)]
+    version_sort: VersionSort,
+    #[serde(skip)]
+    modified: Option<SystemTime>,
+    #[serde(skip)]
+    prefer: Prefer,
 }
 
 impl Environment {
@@ -107,31 +418,46 @@ impl Environment {
 
     /// Replace placeholders in the environment configuration when using a pattern
     fn replace_placeholders(&mut self, value: &str) {
+        self.replace_with(&|s| s.replacen("{}", value, 1));
+    }
+
+    /// Replace named placeholders (e.g. `{arch}`) in the environment configuration
+    fn replace_named_placeholders(&mut self, values: &[(&str, &str)]) {
+        self.replace_with(&|s| {
+            values.iter().fold(s.to_string(), |acc, (key, value)| {
+                acc.replace(&format!("{{{}}}", key), value)
+            })
+        });
+    }
+
+    /// Apply the replacement function to all the configuration values
+    fn replace_with(&mut self, replace_fn: &dyn Fn(&str) -> String) {
         let replace = |s: &mut Option<String>| {
-            *s = s.as_ref().map(|v| v.replacen("{}", value, 1));
+            *s = s.as_deref().map(replace_fn);
         };
         let replace_vec = |v: &mut Option<Vec<String>>| {
             *v = v
                 .as_ref()
-                .map(|items| items.iter().map(|i| i.replacen("{}", value, 1)).collect());
+                .map(|items| items.iter().map(|i| replace_fn(i)).collect());
         };
-        let replace_map = |m: &mut Option<HashMap<String, String>>| {
+        let replace_map = |m: &mut Option<IndexMap<String, String>>| {
             *m = m.as_ref().map(|map| {
                 map.iter()
-                    .map(|(k, v)| (k.clone(), v.replacen("{}", value, 1)))
+                    .map(|(k, v)| (k.clone(), replace_fn(v)))
                     .collect()
             });
         };
-        let replace_map_keys = |m: &mut Option<HashMap<String, String>>| {
+        let replace_map_keys = |m: &mut Option<IndexMap<String, String>>| {
             *m = m.as_ref().map(|map| {
                 map.iter()
-                    .map(|(k, v)| (k.replacen("{}", value, 1), v.replacen("{}", value, 1)))
+                    .map(|(k, v)| (replace_fn(k), replace_fn(v)))
                     .collect()
             });
         };
 
-        self.name = self.name.replacen("{}", value, 1);
+        self.name = replace_fn(&self.name);
         replace(&mut self.global.display);
+        replace(&mut self.global.title);
         replace(&mut self.global.go);
         replace(&mut self.global.script);
         replace_map(&mut self.global.set);
@@ -139,6 +465,23 @@ impl Environment {
         replace_map(&mut self.global.prepend);
         replace_map_keys(&mut self.global.alias);
         replace_vec(&mut self.global.path);
+        if let Some(paths) = &mut self.global.paths {
+            for list in paths.values_mut() {
+                for path in list.prepend.iter_mut().chain(list.append.iter_mut()) {
+                    *path = replace_fn(path);
+                }
+            }
+        }
+        if let Some(reuse) = &mut self.global.reuse {
+            for dependency in reuse {
+                dependency.name = replace_fn(&dependency.name);
+            }
+        }
+        replace_vec(&mut self.provides);
+        replace_vec(&mut self.conflicts);
+        for requirement in self.requires.iter_mut().flatten() {
+            requirement.replace_with(replace_fn);
+        }
     }
 
     /// Fold the shell-specific properties into the global properties
@@ -146,115 +489,230 @@ impl Environment {
         let shell_env = match context.shell {
             Shell::Cmd => self.for_cmd.take(),
             Shell::Powershell => self.for_powershell.take(),
-            Shell::Unknown => panic!("Unsupported shell"),
+            Shell::Unknown => None,
         };
 
         if let Some(shell_props) = shell_env {
             self.global.merge(shell_props);
         }
+
+        // Remove the dependencies not used in this context
+        if let Some(reuse) = &mut self.global.reuse {
+            reuse.retain(|dep| dep.context.as_ref().is_none_or(|c| context.check(c)));
+        }
+    }
+
+    /// Check if this environment conflicts with the given environment name
+    fn conflicts_with(&self, name: &str) -> bool {
+        self.name != name
+            && self.conflicts.as_ref().is_some_and(|conflicts| {
+                conflicts.iter().any(|pattern| {
+                    let regex = format!(
+                        "^{}$",
+                        regex::escape(pattern)
+                            .replace(r"\*", ".*")
+                            .replace(r"\?", ".")
+                    );
+                    Regex::new(&regex).is_ok_and(|re| re.is_match(name))
+                })
+            })
+    }
+
+    /// Check the requirements of the environment, with the variables set up by its
+    /// dependencies, returning all the problems found
+    fn check_requirements(&self, variables: &HashMap<String, String>) -> Result<(), Error> {
+        let errors: Vec<String> = self
+            .requires
+            .iter()
+            .flatten()
+            .filter_map(|requirement| {
+                let mut requirement = requirement.clone();
+                requirement.replace_with(&|value| {
+                    expand_variables(value, variables).unwrap_or_else(|| value.to_string())
+                });
+                requirement.check(variables).err()
+            })
+            .collect();
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(Error::Requirements(format!(
+                "Environment {} cannot be used: {}",
+                self.name,
+                errors.join(", ")
+            )))
+        }
     }
 
     fn display_name(&self) -> &str {
         self.global.display.as_deref().unwrap_or(&self.name)
     }
 
-    /// Sort the environment hashmap by dependencies and return a vector of (key, value) tuples
-    fn sort_env_by_dependencies(env_map: &HashMap<String, String>) -> Vec<(String, String)> {
-        use std::collections::HashSet;
-
-        // Build dependency graph
-        let mut deps: HashMap<String, HashSet<String>> = HashMap::new();
-        for (key, value) in env_map.iter() {
-            let mut set = HashSet::new();
-            for cap in ENV_VAR_REGEX.captures_iter(value) {
-                let dep = cap[1].to_string();
-                if env_map.contains_key(&dep) {
-                    set.insert(dep);
-                }
-            }
-            deps.insert(key.clone(), set);
+    /// Terminal title for this environment, or None if the title is not changed
+    fn title(&self, settings: &Settings) -> Option<String> {
+        if !self.global.update_title.unwrap_or(settings.update_title) {
+            return None;
         }
+        let template = self.global.title.as_deref().unwrap_or(&settings.title);
+        let template = match &self.version {
+            Some(version) => template.replace("{version}", version),
+            None => EMPTY_VERSION_REGEX.replace_all(template, "").into_owned(),
+        };
+        Some(
+            template
+                .replace("{name}", &self.name)
+                .replace("{display}", self.display_name()),
+        )
+    }
 
-        // Topological sort
-        let mut visited = HashSet::new();
-        let mut result = Vec::new();
+    /// Separator of a list variable, if overridden in the configuration
+    fn separator(&self, key: &str) -> Option<&str> {
+        self.global
+            .separator
+            .as_ref()
+            .and_then(|separators| separators.get(key))
+            .map(String::as_str)
+    }
+
+    /// Sort the environment variables by dependencies and return a vector of (key, value)
+    /// tuples. The declaration order is kept, unless a variable is referenced with `${VAR}`
+    /// by another one declared before it.
+    fn sort_env_by_dependencies(env_map: &IndexMap<String, String>) -> Vec<(String, String)> {
+        use std::collections::HashSet;
 
         fn visit(
             key: &str,
-            env_map: &HashMap<String, String>,
-            deps: &HashMap<String, HashSet<String>>,
+            env_map: &IndexMap<String, String>,
             visited: &mut HashSet<String>,
             result: &mut Vec<(String, String)>,
         ) {
             if !visited.insert(key.to_string()) {
                 return;
             }
-            if let Some(dep_set) = deps.get(key) {
-                for dep in dep_set {
-                    visit(dep, env_map, deps, visited, result);
+            if let Some(value) = env_map.get(key) {
+                // Dependencies first, in the order they are referenced
+                for cap in ENV_VAR_REGEX.captures_iter(value) {
+                    visit(&cap[1], env_map, visited, result);
                 }
-            }
-            if let Some(val) = env_map.get(key) {
-                result.push((key.to_string(), val.clone()));
+                result.push((key.to_string(), value.clone()));
             }
         }
 
-        let mut keys: Vec<_> = env_map.keys().collect();
-        keys.sort(); // for deterministic order
-
-        for key in keys {
-            visit(key, env_map, &deps, &mut visited, &mut result);
+        let mut visited = HashSet::new();
+        let mut result = Vec::new();
+        for key in env_map.keys() {
+            visit(key, env_map, &mut visited, &mut result);
         }
 
         result
     }
 
-    /// Print the environment using the provided ShellPrinter
-    pub fn print(&self, printer: &dyn ShellPrinter) {
+    /// Print the environment using the provided ShellPrinter, the name of the environment
+    /// being set in the prompt variable
+    pub fn print(&self, printer: &dyn ShellPrinter, prompt_var: &str) {
         printer.start(&self.name, self.display_name());
 
-        let process_map = |map: &Option<HashMap<String, String>>, action: &dyn Fn(&str, &str)| {
+        // The printers escape the values and replace the `${VAR}` references themselves
+        let process_map = |map: &Option<IndexMap<String, String>>, action: &dyn Fn(&str, &str)| {
             if let Some(map) = map {
                 for (key, value) in Self::sort_env_by_dependencies(map) {
-                    let v = Self::substitute_env_vars(&value, printer);
-                    action(&key, &v);
+                    action(&key, &value);
                 }
             }
         };
 
         process_map(&self.global.set, &|k, v| printer.set(k, v));
-        process_map(&self.global.append, &|k, v| printer.append(k, v));
-        process_map(&self.global.prepend, &|k, v| printer.prepend(k, v));
+        process_map(&self.global.append, &|k, v| {
+            printer.append(k, v, self.separator(k))
+        });
+        process_map(&self.global.prepend, &|k, v| {
+            printer.prepend(k, v, self.separator(k))
+        });
 
         if let Some(alias) = &self.global.alias {
             for (name, command) in alias {
-                let cmd = Self::substitute_env_vars(command, printer);
-                printer.alias(name, &cmd);
+                printer.alias(name, command);
             }
         }
 
         if let Some(paths) = &self.global.path {
             for path in paths {
-                let p = Self::substitute_env_vars(path, printer);
-                printer.prepend_path(&p);
+                printer.prepend_path(path, self.separator("PATH"));
             }
         }
 
+        if let Some(paths) = &self.global.paths {
+            for (key, list) in paths {
+                if !list.prepend.is_empty() {
+                    printer.prepend_paths(key, &list.prepend, self.separator(key));
+                }
+                if !list.append.is_empty() {
+                    printer.append_paths(key, &list.append, self.separator(key));
+                }
+            }
+        }
+
+        // Scripts are raw shell code, only the references are replaced
         if let Some(script) = &self.global.script {
             let s = Self::substitute_env_vars(script.trim(), printer);
             printer.run(&s);
         }
 
         if let Some(go) = &self.global.go {
-            let g = Self::substitute_env_vars(go, printer);
-            printer.go(&g);
+            printer.go(go);
         }
 
-        // Set the USE_PROMPT environment variable
-        printer.set("USE_PROMPT", self.name.as_str());
+        // Set the prompt environment variable, USE_PROMPT by default
+        printer.set(prompt_var, self.name.as_str());
         printer.finish();
     }
 
+    /// Create matrix-based environments, one for each combination of the matrix values.
+    /// Each axis is available as a named placeholder (e.g. `{arch}`), and is appended to
+    /// the name if not already used in it.
+    pub fn create_matrix_envs(self) -> Vec<Environment> {
+        let matrix = match &self.matrix {
+            Some(m) => m,
+            None => return vec![self],
+        };
+
+        let mut combinations: Vec<Vec<(&str, &str)>> = vec![Vec::new()];
+        for (axis, values) in matrix {
+            combinations = combinations
+                .iter()
+                .flat_map(|combination| {
+                    values.iter().map(move |value| {
+                        let mut combination = combination.clone();
+                        combination.push((axis.as_str(), value.as_str()));
+                        combination
+                    })
+                })
+                .collect();
+        }
+
+        combinations
+            .into_iter()
+            .map(|combination| {
+                let mut new_env = self.clone();
+                for (axis, value) in &combination {
+                    if !new_env.name.contains(&format!("{{{}}}", axis)) {
+                        new_env.name = format!("{}-{}", new_env.name, value);
+                    }
+                }
+                new_env.replace_named_placeholders(&combination);
+                if let Some(pattern) = &mut new_env.pattern {
+                    for (axis, value) in &combination {
+                        let placeholder = format!("{{{}}}", axis);
+                        pattern.path = pattern.path.replace(&placeholder, value);
+                        pattern.regex = pattern.regex.replace(&placeholder, value);
+                    }
+                }
+                new_env.matrix = None;
+                new_env
+            })
+            .collect()
+    }
+
     /// Create pattern-based environments from the given environment
     pub fn create_pattern_envs(&self) -> Vec<Environment> {
         let mut pattern_envs = Vec::new();
@@ -273,6 +731,14 @@ impl Environment {
             Err(_) => return pattern_envs,
         };
 
+        let version_re = match pattern.version_from.as_ref().and_then(|v| v.regex.as_ref()) {
+            Some(regex) => match Regex::new(regex) {
+                Ok(r) => Some(r),
+                Err(_) => return pattern_envs,
+            },
+            None => None,
+        };
+
         let entries = match fs::read_dir(path) {
             Ok(e) => e,
             Err(_) => return pattern_envs,
@@ -288,8 +754,17 @@ impl Environment {
                     new_env.replace_placeholders(capture.as_str());
                 }
 
-                new_env.version = captures.get(1).map(|m| m.as_str().to_string());
+                // The version is read from a file inside the entry if requested,
+                // using the first capture otherwise
+                new_env.version = pattern
+                    .version_from
+                    .as_ref()
+                    .and_then(|v| v.read(&entry.path(), version_re.as_ref()))
+                    .or_else(|| captures.get(1).map(|m| m.as_str().to_string()));
                 new_env.original_name = Some(self.name.to_string());
+                new_env.version_sort = pattern.version_sort;
+                new_env.prefer = pattern.prefer.clone();
+                new_env.modified = entry.metadata().and_then(|m| m.modified()).ok();
                 new_env.pattern = None;
 
                 pattern_envs.push(new_env);
@@ -300,6 +775,143 @@ impl Environment {
     }
 }
 
+/// Get the list of environments already used in the current shell
+fn active_environments() -> Vec<String> {
+    std::env::var(ACTIVE_ENVS_VAR)
+        .map(|envs| {
+            envs.split(',')
+                .filter(|e| !e.is_empty())
+                .map(String::from)
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Collect the variables changed by the environments, in the order they are set up. The
+/// values appended or prepended are added to the current value of the process environment.
+fn collect_variables(envs: &[&Environment]) -> HashMap<String, String> {
+    let mut variables = HashMap::new();
+    let expand = |value: &str, variables: &HashMap<String, String>| {
+        expand_variables(value, variables).unwrap_or_else(|| value.to_string())
+    };
+    for env in envs {
+        let separator = |key: &str| env.separator(key).unwrap_or(detect_os().path_separator());
+        if let Some(set) = &env.global.set {
+            for (key, value) in Environment::sort_env_by_dependencies(set) {
+                let value = expand(&value, &variables);
+                variables.insert(key, value);
+            }
+        }
+        for (key, value) in env.global.append.iter().flatten() {
+            let value = expand(value, &variables);
+            add_to_list(&mut variables, key, value, separator(key), false);
+        }
+        for (key, value) in env.global.prepend.iter().flatten() {
+            let value = expand(value, &variables);
+            add_to_list(&mut variables, key, value, separator(key), true);
+        }
+        for path in env.global.path.iter().flatten() {
+            let path = expand(path, &variables);
+            add_to_list(&mut variables, "PATH", path, separator("PATH"), true);
+        }
+        for (key, list) in env.global.paths.iter().flatten() {
+            for (paths, prepend) in [(&list.prepend, true), (&list.append, false)] {
+                if !paths.is_empty() {
+                    let paths: Vec<_> = paths.iter().map(|p| expand(p, &variables)).collect();
+                    let value = paths.join(separator(key));
+                    add_to_list(&mut variables, key, value, separator(key), prepend);
+                }
+            }
+        }
+    }
+    variables
+}
+
+/// Add a value to a list variable, without any separator if the list is empty
+fn add_to_list(
+    variables: &mut HashMap<String, String>,
+    key: &str,
+    value: String,
+    separator: &str,
+    prepend: bool,
+) {
+    let current = variables
+        .get(key)
+        .cloned()
+        .or_else(|| std::env::var(key).ok())
+        .unwrap_or_default();
+    let list = match (current.is_empty(), prepend) {
+        (true, _) => value,
+        (false, true) => format!("{}{}{}", value, separator, current),
+        (false, false) => format!("{}{}{}", current, separator, value),
+    };
+    variables.insert(key.to_string(), list);
+}
+
+/// Replace ${VAR} placeholders using the given variables or the process environment.
+/// Return None if a variable can't be resolved.
+fn expand_variables(value: &str, variables: &HashMap<String, String>) -> Option<String> {
+    let mut resolved = true;
+    let result = ENV_VAR_REGEX.replace_all(value, |caps: &regex::Captures| {
+        variables
+            .get(&caps[1])
+            .cloned()
+            .or_else(|| std::env::var(&caps[1]).ok())
+            .unwrap_or_else(|| {
+                resolved = false;
+                String::new()
+            })
+    });
+    resolved.then(|| result.into_owned())
+}
+
+/// Remove the null values, i.e. the properties not set
+fn remove_nulls(value: &mut serde_json::Value) {
+    match value {
+        serde_json::Value::Object(map) => {
+            map.retain(|_, v| !v.is_null());
+            map.values_mut().for_each(remove_nulls);
+        }
+        serde_json::Value::Array(items) => items.iter_mut().for_each(remove_nulls),
+        _ => {}
+    }
+}
+
+/// Content of the configuration file: the environments, and the optional `settings` section
+struct ConfigFile {
+    environments: HashMap<String, Environment>,
+}
+
+impl<'de> Deserialize<'de> for ConfigFile {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct ConfigFileVisitor;
+
+        impl<'de> Visitor<'de> for ConfigFileVisitor {
+            type Value = ConfigFile;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("a map of environments")
+            }
+
+            fn visit_map<A: de::MapAccess<'de>>(self, mut map: A) -> Result<ConfigFile, A::Error> {
+                let mut environments = HashMap::new();
+                while let Some(name) = map.next_key::<String>()? {
+                    if name == SETTINGS_KEY {
+                        // Settings are read separately, only check them here
+                        let settings: FileSettings = map.next_value()?;
+                        settings.validate().map_err(de::Error::custom)?;
+                    } else {
+                        environments.insert(name, map.next_value()?);
+                    }
+                }
+                Ok(ConfigFile { environments })
+            }
+        }
+
+        deserializer.deserialize_map(ConfigFileVisitor)
+    }
+}
+
 /// Struct to hold the list of environments
 #[derive(Debug, Clone, PartialEq)]
 pub struct Config {
@@ -308,13 +920,12 @@ pub struct Config {
 
 impl Config {
     /// Create a new Config struct
-    pub fn new(context: &Context) -> Result<Self, String> {
+    pub fn new(context: &Context) -> Result<Self, Error> {
         let path = Path::new(&context.config_path);
         if !path.exists() {
-            return Err(format!("Config file not found at {}", path.display()));
+            return Err(Error::ConfigNotFound(path.to_path_buf()));
         }
-        let environments = Self::read_config_file(path, context)
-            .map_err(|e| format!("Failed to read config file: {}", e))?;
+        let environments = Self::read_config_file(path, context)?;
         Ok(Self { environments })
     }
 
@@ -332,39 +943,176 @@ impl Config {
         name: &str,
         settings: &Settings,
         shell_printer: &dyn ShellPrinter,
-    ) -> Result<(), String> {
+    ) -> Result<(), Error> {
         // Find the name of all environments needed to be used
-        let envs = self.resolve_dependencies(name)?;
+        let envs = self.resolve_dependencies(name, settings)?;
+
+        let active_envs = active_environments();
+        self.check_conflicts(&envs, &active_envs)?;
+        // Check all requirements first, to avoid a partial setup. They can be met by the
+        // environments set up before.
+        for (i, env) in envs.iter().enumerate() {
+            env.check_requirements(&collect_variables(&envs[..i]))?;
+        }
 
         for env in &envs {
-            env.print(shell_printer);
+            env.print(shell_printer, &settings.prompt_var);
         }
 
-        if settings.update_title {
-            shell_printer.change_title(name);
+        // Keep track of all environments used in the shell
+        let mut used_envs = active_envs;
+        for env in &envs {
+            if !used_envs.contains(&env.name) {
+                used_envs.push(env.name.clone());
+            }
         }
+        shell_printer.set(ACTIVE_ENVS_VAR, &used_envs.join(","));
 
-        // All good, just show a small message
+        // All good, change the title and show a small message
         if let Some(env) = envs.last() {
+            if let Some(title) = env.title(settings) {
+                shell_printer.change_title(&title);
+            }
             shell_printer.finalize(&env.name, env.display_name());
         }
         Ok(())
     }
 
+    /// Check that no environment conflicts with another one in the list, or with
+    /// an environment already active in the shell
+    fn check_conflicts(&self, envs: &[&Environment], active_envs: &[String]) -> Result<(), Error> {
+        for (i, env) in envs.iter().enumerate() {
+            for other in &envs[i + 1..] {
+                if env.conflicts_with(&other.name) || other.conflicts_with(&env.name) {
+                    return Err(Error::Conflict(format!(
+                        "Environment {} conflicts with {}",
+                        other.name, env.name
+                    )));
+                }
+            }
+
+            for active in active_envs {
+                let active_conflicts = self
+                    .environments
+                    .iter()
+                    .find(|e| e.name == *active)
+                    .is_some_and(|e| e.conflicts_with(&env.name));
+                if env.conflicts_with(active) || active_conflicts {
+                    return Err(Error::Conflict(format!(
+                        "Environment {} conflicts with {}, which is already active",
+                        env.name, active
+                    )));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Find the environment matching the given name, which can be partial.
+    /// An exact match always wins, then an environment marked as default, then
+    /// the version preferred by the pattern of the first matching environment.
+    fn find_environment(&self, name: &str) -> Option<&Environment> {
+        if let Some(env) = self.environments.iter().find(|env| env.name == name) {
+            return Some(env);
+        }
+
+        let candidates: Vec<&Environment> = self
+            .environments
+            .iter()
+            .filter(|env| env.name.starts_with(name))
+            .collect();
+        // A default pattern gives the flag to all its environments, the preferred version
+        // is chosen within them
+        let first = *candidates
+            .iter()
+            .find(|env| env.default)
+            .or(candidates.first())?;
+        if first.original_name.is_none() {
+            return Some(first);
+        }
+        // Environments are already sorted from the newest to the oldest version
+        let mut versions = candidates
+            .iter()
+            .filter(|env| env.original_name == first.original_name);
+        let preferred = match &first.prefer {
+            Prefer::Newest => None,
+            Prefer::Oldest => versions.next_back(),
+            Prefer::Constraint(req) => versions.find(|env| {
+                env.version
+                    .as_deref()
+                    .and_then(parse_lenient)
+                    .is_some_and(|v| req.matches(&v))
+            }),
+        };
+        Some(preferred.copied().unwrap_or(first))
+    }
+
+    /// Find the environment providing the given capability, using the provider
+    /// set in the settings if available, or the one with the highest priority
+    fn find_provider(&self, capability: &str, settings: &Settings) -> Option<&Environment> {
+        let providers: Vec<&Environment> = self
+            .environments
+            .iter()
+            .filter(|env| {
+                env.provides
+                    .as_ref()
+                    .is_some_and(|p| p.iter().any(|c| c == capability))
+            })
+            .collect();
+
+        if let Some(preferred) = settings.providers.get(capability)
+            && let Some(env) = providers
+                .iter()
+                .find(|env| env.name == *preferred)
+                .or_else(|| providers.iter().find(|env| env.name.starts_with(preferred)))
+        {
+            return Some(env);
+        }
+
+        providers
+            .into_iter()
+            .min_by_key(|env| std::cmp::Reverse(env.priority))
+    }
+
+    /// Find the environment for a name given on the command line or in `use`: an environment
+    /// with exactly this name, then a provider of this capability, then a partial name
+    fn find_by_name(&self, name: &str, settings: &Settings) -> Option<&Environment> {
+        self.environments
+            .iter()
+            .find(|env| env.name == name)
+            .or_else(|| self.find_provider(name, settings))
+            .or_else(|| self.find_environment(name))
+    }
+
     /// Resolve dependencies for the given environment name
     /// and return a vector of environment names
-    fn resolve_dependencies<'a>(&'a self, name: &str) -> Result<Vec<&'a Environment>, String> {
+    fn resolve_dependencies<'a>(
+        &'a self,
+        name: &str,
+        settings: &Settings,
+    ) -> Result<Vec<&'a Environment>, Error> {
         let env = self
-            .environments
-            .iter()
-            .find(|env| env.name == name || env.name.starts_with(name))
-            .ok_or_else(|| format!("Environment {} not found", name))?;
+            .find_by_name(name, settings)
+            .ok_or_else(|| Error::EnvironmentNotFound(name.to_string()))?;
+        self.resolve_environment_dependencies(env, settings)
+    }
 
+    /// Resolve dependencies for the given environment
+    /// and return a vector of environments, ending with the environment itself
+    fn resolve_environment_dependencies<'a>(
+        &'a self,
+        env: &'a Environment,
+        settings: &Settings,
+    ) -> Result<Vec<&'a Environment>, Error> {
         let mut current_envs = Vec::new();
 
         if let Some(reuse) = &env.global.reuse {
-            for env_name in reuse {
-                let deps = self.resolve_dependencies(env_name)?;
+            for dependency in reuse {
+                let dep_env = match self.find_dependency(env, dependency, settings)? {
+                    Some(dep_env) => dep_env,
+                    None => continue,
+                };
+                let deps = self.resolve_environment_dependencies(dep_env, settings)?;
                 for dep in deps {
                     if !current_envs.contains(&dep) {
                         current_envs.push(dep);
@@ -376,15 +1124,108 @@ impl Config {
         Ok(current_envs)
     }
 
+    /// Find the environment for the given dependency, checking its version constraint.
+    /// Return None if an optional dependency can't be found.
+    fn find_dependency(
+        &self,
+        env: &Environment,
+        dependency: &Dependency,
+        settings: &Settings,
+    ) -> Result<Option<&Environment>, Error> {
+        let found = match &dependency.version {
+            Some(req) => self.environments.iter().find(|env| {
+                env.name.starts_with(&dependency.name)
+                    && env
+                        .version
+                        .as_deref()
+                        .and_then(parse_lenient)
+                        .is_some_and(|v| req.matches(&v))
+            }),
+            None => self.find_by_name(&dependency.name, settings),
+        };
+
+        match found {
+            Some(found) => Ok(Some(found)),
+            None if dependency.optional => Ok(None),
+            None => Err(Error::DependencyNotFound {
+                env: env.name.clone(),
+                dependency: match &dependency.version {
+                    Some(req) => format!("{} {}", dependency.name, req),
+                    None => dependency.name.clone(),
+                },
+            }),
+        }
+    }
+
+    /// Generate the JSON Schema of the configuration file, from the configuration types
+    pub fn schema() -> String {
+        let mut generator = schemars::SchemaGenerator::default();
+        let settings = generator.subschema_for::<FileSettings>();
+        let mut schema = generator.into_root_schema_for::<HashMap<String, Environment>>();
+        schema.insert("title".into(), "use configuration".into());
+        schema.insert(
+            "properties".into(),
+            serde_json::json!({ SETTINGS_KEY: settings }),
+        );
+        // Serde aliases are not part of the generated schema
+        if let Some(properties) = schema.pointer_mut("/$defs/Environment/properties")
+            && let Some(for_powershell) = properties.get("for_powershell").cloned()
+        {
+            properties["for_pwsh"] = for_powershell;
+        }
+        serde_json::to_string_pretty(&schema).expect("JSON Schema is always serializable")
+    }
+
+    /// Return the environments after inheritance, shell folding, `for` filtering, matrix and
+    /// pattern expansion, or only the given environment
+    pub fn show(
+        &self,
+        name: Option<&str>,
+        settings: &Settings,
+    ) -> Result<serde_json::Value, Error> {
+        let envs = match name {
+            Some(name) => vec![
+                self.find_by_name(name, settings)
+                    .ok_or_else(|| Error::EnvironmentNotFound(name.to_string()))?,
+            ],
+            None => self.environments.iter().collect(),
+        };
+
+        let mut result = serde_json::Map::new();
+        for env in envs {
+            // Shell-specific properties are already folded, or not used in this shell
+            let mut env = Environment {
+                for_cmd: None,
+                for_powershell: None,
+                ..env.clone()
+            };
+            // Dependencies may all be filtered out by `for`
+            if env.global.reuse == Some(Vec::new()) {
+                env.global.reuse = None;
+            }
+            let mut value = serde_json::to_value(&env).expect("Environment is always serializable");
+            remove_nulls(&mut value);
+            result.insert(env.name, value);
+        }
+        Ok(serde_json::Value::Object(result))
+    }
+
     /// Read the config file and return a vector of environments
     pub fn read_config_file(
         file_path: &Path,
         context: &Context,
-    ) -> Result<Vec<Environment>, Box<dyn std::error::Error>> {
-        let file = fs::File::open(file_path)?;
-        let reader = BufReader::new(file);
-        let env_hash: HashMap<String, Environment> = yaml_serde::from_reader(reader)?;
-        Self::create_env_vector(context, env_hash)
+    ) -> Result<Vec<Environment>, Error> {
+        let (source, env_hash) = Self::read_env_hash(file_path)?;
+        Self::create_env_vector(context, &source, env_hash)
+    }
+
+    /// Read the environments defined in the config file, as written in the file
+    fn read_env_hash(
+        file_path: &Path,
+    ) -> Result<(SourceFile, HashMap<String, Environment>), Error> {
+        let source = SourceFile::read(file_path)?;
+        let config_file: ConfigFile = ConfigFormat::from_path(file_path).parse(&source)?;
+        Ok((source, config_file.environments))
     }
 
     /// Read the config file from a string
@@ -393,24 +1234,29 @@ impl Config {
     fn read_config_from_string(
         content: &str,
         context: &Context,
-    ) -> Result<Vec<Environment>, Box<dyn std::error::Error>> {
-        let env_hash: HashMap<String, Environment> = yaml_serde::from_str(content)?;
-        Self::create_env_vector(context, env_hash)
+    ) -> Result<Vec<Environment>, Error> {
+        let source = SourceFile {
+            path: PathBuf::from("use.yaml"),
+            content: content.to_string(),
+        };
+        let config_file: ConfigFile =
+            yaml_serde::from_str(content).map_err(|e| source.yaml_error(e))?;
+        Self::create_env_vector(context, &source, config_file.environments)
     }
 
     /// Create a vector of environments from the given hash map
     fn create_env_vector(
         context: &Context,
+        source: &SourceFile,
         env_hash: HashMap<String, Environment>,
-    ) -> Result<Vec<Environment>, Box<dyn std::error::Error>> {
+    ) -> Result<Vec<Environment>, Error> {
+        let env_hash = Self::prepare_envs(context, source, env_hash)?;
+
         let mut envs: Vec<Environment> = env_hash
-            .into_iter()
-            .map(|(name, mut env)| {
-                env.name = name;
-                env.fold(context);
-                env
-            })
+            .into_values()
+            .filter(|env| !env.is_abstract)
             .filter(|env| env.context.as_ref().is_none_or(|c| context.check(c)))
+            .flat_map(|env| env.create_matrix_envs())
             .collect();
 
         // Process pattern-based environments
@@ -427,22 +1273,96 @@ impl Config {
         Ok(envs)
     }
 
-    /// Sort the environments, using the original key and version
-    /// to determine the order. If the original key is the same, sort by version.
-    fn sort_environments(environments: &mut [Environment]) {
-        environments.sort_by(|a, b| {
-            if let (Some(key_a), Some(key_b)) = (&a.original_name, &b.original_name)
-                && key_a == key_b
-                && let (Some(ver_a), Some(ver_b)) = (&a.version, &b.version)
-                && let (Ok(v_a), Ok(v_b)) = (Version::parse(ver_a), Version::parse(ver_b))
-            {
-                return v_b.cmp(&v_a); // Newer versions first
-            }
+    /// Name the environments, fold the shell-specific properties and resolve inheritance
+    fn prepare_envs(
+        context: &Context,
+        source: &SourceFile,
+        env_hash: HashMap<String, Environment>,
+    ) -> Result<HashMap<String, Environment>, Error> {
+        let mut env_hash: HashMap<String, Environment> = env_hash
+            .into_iter()
+            .map(|(name, mut env)| {
+                env.name = name.clone();
+                env.fold(context);
+                (name, env)
+            })
+            .collect();
 
-            a.name.cmp(&b.name) // Default lexicographical sort
-        });
+        let names: Vec<String> = env_hash.keys().cloned().collect();
+        for name in names {
+            Self::resolve_extends(&name, &mut env_hash, &mut Vec::new())
+                .map_err(|(env, message)| source.error_at_key(&env, &message))?;
+        }
+
+        Ok(env_hash)
     }
-}
+
+    /// Merge the properties of the extended environment into the given environment,
+    /// resolving the extended environment first if it extends another one.
+    /// On error, return the name of the environment in error with the message.
+    fn resolve_extends(
+        name: &str,
+        env_hash: &mut HashMap<String, Environment>,
+        chain: &mut Vec<String>,
+    ) -> Result<(), (String, String)> {
+        let base_name = match env_hash.get(name).and_then(|env| env.extends.clone()) {
+            Some(base_name) => base_name,
+            None => return Ok(()),
+        };
+        if chain.iter().any(|n| n == name) {
+            return Err((
+                name.to_string(),
+                format!(
+                    "Circular extends between environments: {} -> {}",
+                    chain.join(" -> "),
+                    name
+                ),
+            ));
+        }
+        if !env_hash.contains_key(&base_name) {
+            return Err((
+                name.to_string(),
+                format!(
+                    "Environment {} extends unknown environment {}",
+                    name, base_name
+                ),
+            ));
+        }
+
+        chain.push(name.to_string());
+        Self::resolve_extends(&base_name, env_hash, chain)?;
+        chain.pop();
+
+        let mut global = env_hash[&base_name].global.clone();
+        let env = env_hash.get_mut(name).expect("environment exists");
+        global.merge(std::mem::take(&mut env.global));
+        env.global = global;
+        env.extends = None;
+        Ok(())
+    }
+
+    /// Sort the environments, using the original key and version
+    /// to determine the order. If the original key is the same, sort by version,
+    /// using the strategy defined by the pattern.
+    fn sort_environments(environments: &mut [Environment]) {
+        environments.sort_by(|a, b| {
+            if let (Some(key_a), Some(key_b)) = (&a.original_name, &b.original_name)
+                && key_a == key_b
+            {
+                if a.version_sort == VersionSort::Mtime
+                    && let (Some(time_a), Some(time_b)) = (a.modified, b.modified)
+                {
+                    return time_b.cmp(&time_a); // Most recent first
+                }
+                if let (Some(ver_a), Some(ver_b)) = (&a.version, &b.version) {
+                    return a.version_sort.compare(ver_a, ver_b); // Newer versions first
+                }
+            }
+
+            a.name.cmp(&b.name) // Default lexicographical sort
+        });
+    }
+}
 
 #[cfg(test)]
 mod tests {
@@ -451,10 +1371,76 @@ mod tests {
     use crate::context::{Context, OperatingSystem};
     use std::ffi::OsString;
 
+    /// Context of the tests, the cmd shell on Windows
+    fn cmd_context() -> Context {
+        Context {
+            os: OperatingSystem::Windows,
+            shell: Shell::Cmd,
+            config_path: OsString::new(),
+        }
+    }
+
+    /// Printer recording the shell commands, to check the output of print_env
+    #[derive(Default)]
+    struct RecordingPrinter {
+        lines: std::cell::RefCell<Vec<String>>,
+    }
+
+    impl RecordingPrinter {
+        fn record(&self, line: String) {
+            self.lines.borrow_mut().push(line);
+        }
+    }
+
+    impl ShellPrinter for RecordingPrinter {
+        fn run(&self, script: &str) {
+            self.record(format!("run {}", script));
+        }
+        fn echo(&self, message: &str) {
+            self.record(format!("echo {}", message));
+        }
+        fn set(&self, key: &str, value: &str) {
+            self.record(format!("set {}={}", key, value));
+        }
+        fn append(&self, key: &str, value: &str, _separator: Option<&str>) {
+            self.record(format!("append {}={}", key, value));
+        }
+        fn prepend(&self, key: &str, value: &str, _separator: Option<&str>) {
+            self.record(format!("prepend {}={}", key, value));
+        }
+        fn prepend_path(&self, path: &str, _separator: Option<&str>) {
+            self.record(format!("path {}", path));
+        }
+        fn prepend_paths(&self, key: &str, paths: &[String], _separator: Option<&str>) {
+            self.record(format!("prepend {}={:?}", key, paths));
+        }
+        fn append_paths(&self, key: &str, paths: &[String], _separator: Option<&str>) {
+            self.record(format!("append {}={:?}", key, paths));
+        }
+        fn go(&self, path: &str) {
+            self.record(format!("go {}", path));
+        }
+        fn alias(&self, name: &str, command: &str) {
+            self.record(format!("alias {}={}", name, command));
+        }
+        fn change_title(&self, title: &str) {
+            self.record(format!("title {}", title));
+        }
+        fn env_variable(&self, env: &str) -> String {
+            format!("${}", env)
+        }
+    }
+
+    /// Read the configuration from a YAML string, in the cmd context
+    fn config(yaml: &str) -> Config {
+        let envs = Config::read_config_from_string(yaml, &cmd_context()).unwrap();
+        Config { environments: envs }
+    }
+
     #[test]
     fn test_sort_env_by_dependencies() {
         // Multiple dependencies between keys
-        let mut env_map = HashMap::new();
+        let mut env_map = IndexMap::new();
         env_map.insert("KEY1".to_string(), "foo/${KEY2}/${KEY4}".to_string());
         env_map.insert("KEY2".to_string(), "foo".to_string());
         env_map.insert("KEY3".to_string(), "foo/${KEY2}".to_string());
@@ -468,7 +1454,7 @@ mod tests {
     #[test]
     fn test_sort_env_by_dependencies_with_external() {
         // Dependencies with external
-        let mut env_map = HashMap::new();
+        let mut env_map = IndexMap::new();
         env_map.insert(
             "KEY1".to_string(),
             "foo/${KEY2}/${KEY3}/${EXTERNAL}".to_string(),
@@ -484,7 +1470,7 @@ mod tests {
     #[test]
     fn test_sort_env_by_dependencies_with_circular_dependencies() {
         // Dependencies with circular references
-        let mut env_map = HashMap::new();
+        let mut env_map = IndexMap::new();
         env_map.insert("KEY1".to_string(), "foo/${KEY2}/${EXTERNAL}".to_string());
         env_map.insert("KEY2".to_string(), "foo/${KEY3}/${EXTERNAL}".to_string());
         env_map.insert("KEY3".to_string(), "foo/${KEY1}/${EXTERNAL}".to_string());
@@ -498,6 +1484,43 @@ mod tests {
         assert!(keys.contains(&"KEY3".to_string()));
     }
 
+    #[test]
+    fn test_declaration_order_from_yaml() {
+        let yaml = r#"
+base:
+  abstract: true
+  set:
+    Z_VAR: "z"
+    B_VAR: "${C_VAR}/b"
+    C_VAR: "c"
+  alias:
+    zz: "z.exe"
+    aa: "a.exe"
+tool:
+  extends: base
+  set:
+    A_VAR: "a"
+    Z_VAR: "tool"
+  alias:
+    mm: "m.exe"
+"#;
+        let config = config(yaml);
+        let env = config.find_environment("tool").unwrap();
+
+        // Declaration order, overridden keys keeping their position, and dependencies first
+        let set = env.global.set.as_ref().unwrap();
+        let keys: Vec<_> = set.keys().map(String::as_str).collect();
+        assert_eq!(keys, vec!["Z_VAR", "B_VAR", "C_VAR", "A_VAR"]);
+        let ordered = Environment::sort_env_by_dependencies(set);
+        let ordered_keys: Vec<_> = ordered.iter().map(|(k, _)| k.as_str()).collect();
+        assert_eq!(ordered_keys, vec!["Z_VAR", "C_VAR", "B_VAR", "A_VAR"]);
+        assert_eq!(ordered[0].1, "tool");
+
+        let alias = env.global.alias.as_ref().unwrap();
+        let names: Vec<_> = alias.keys().map(String::as_str).collect();
+        assert_eq!(names, vec!["zz", "aa", "mm"]);
+    }
+
     #[test]
     fn test_replace_placeholders() {
         let mut env = Environment {
@@ -506,49 +1529,56 @@ mod tests {
             pattern: None,
             global: CommonProperties {
                 display: Some("Display {}".to_string()),
+                title: Some("Title {}".to_string()),
+                update_title: None,
                 script: Some("echo {}".to_string()),
-                set: Some(HashMap::from([("KEY".to_string(), "value-{}".to_string())])),
-                append: Some(HashMap::from([(
+                set: Some(IndexMap::from([(
+                    "KEY".to_string(),
+                    "value-{}".to_string(),
+                )])),
+                append: Some(IndexMap::from([(
                     "APPEND".to_string(),
                     "append-{}".to_string(),
                 )])),
-                prepend: Some(HashMap::from([(
+                prepend: Some(IndexMap::from([(
                     "PREPEND".to_string(),
                     "prepend-{}".to_string(),
                 )])),
+                separator: None,
                 path: Some(vec!["path/to/{}".to_string()]),
+                paths: None,
                 reuse: None,
                 go: Some("go-to-{}".to_string()),
-                aliases: None,
+                alias: None,
             },
             for_cmd: None,
             for_powershell: None,
-            version: None,
-            original_name: None,
+            ..Default::default()
         };
 
         env.replace_placeholders("123");
 
         assert_eq!(env.name, "test-123");
         assert_eq!(env.global.display, Some("Display 123".to_string()));
+        assert_eq!(env.global.title, Some("Title 123".to_string()));
         assert_eq!(env.global.script, Some("echo 123".to_string()));
         assert_eq!(
             env.global.set,
-            Some(HashMap::from([(
+            Some(IndexMap::from([(
                 "KEY".to_string(),
                 "value-123".to_string()
             )]))
         );
         assert_eq!(
             env.global.append,
-            Some(HashMap::from([(
+            Some(IndexMap::from([(
                 "APPEND".to_string(),
                 "append-123".to_string()
             )]))
         );
         assert_eq!(
             env.global.prepend,
-            Some(HashMap::from([(
+            Some(IndexMap::from([(
                 "PREPEND".to_string(),
                 "prepend-123".to_string()
             )]))
@@ -565,19 +1595,22 @@ mod tests {
             pattern: None,
             global: CommonProperties {
                 display: Some("Display {} multiple {}".to_string()),
+                title: None,
+                update_title: None,
                 script: Some("echo {} twice {}".to_string()),
                 set: None,
                 append: None,
                 prepend: None,
+                separator: None,
                 path: None,
+                paths: None,
                 reuse: None,
                 go: None,
-                aliases: None,
+                alias: None,
             },
             for_cmd: None,
             for_powershell: None,
-            version: None,
-            original_name: None,
+            ..Default::default()
         };
 
         env.replace_placeholders("123");
@@ -600,48 +1633,51 @@ mod tests {
             pattern: None,
             global: CommonProperties {
                 display: Some("Global Display".to_string()),
+                title: None,
+                update_title: None,
                 script: None,
-                set: Some(HashMap::from([(
+                set: Some(IndexMap::from([(
                     "GLOBAL_KEY".to_string(),
                     "global_value".to_string(),
                 )])),
-                append: Some(HashMap::from([(
+                append: Some(IndexMap::from([(
                     "GLOBAL_APPEND".to_string(),
                     "global_append".to_string(),
                 )])),
                 prepend: None,
+                separator: None,
                 path: Some(vec!["global/path".to_string()]),
-                reuse: Some(vec!["global_reuse".to_string()]),
+                paths: None,
+                reuse: Some(vec!["global_reuse".into()]),
                 go: None,
-                aliases: None,
+                alias: None,
             },
             for_cmd: Some(CommonProperties {
                 display: Some("CMD Display".to_string()),
+                title: None,
+                update_title: None,
                 script: Some("cmd.exe /c echo test".to_string()),
-                set: Some(HashMap::from([
+                set: Some(IndexMap::from([
                     ("CMD_KEY".to_string(), "cmd_value".to_string()),
                     ("GLOBAL_KEY".to_string(), "cmd_override".to_string()),
                 ])),
                 append: None,
-                prepend: Some(HashMap::from([(
+                prepend: Some(IndexMap::from([(
                     "CMD_PREPEND".to_string(),
                     "cmd_prepend".to_string(),
                 )])),
+                separator: None,
                 path: Some(vec!["cmd/path".to_string()]),
-                reuse: Some(vec!["cmd_reuse".to_string()]),
+                paths: None,
+                reuse: Some(vec!["cmd_reuse".into()]),
                 go: Some("cmd_go".to_string()),
-                aliases: None,
+                alias: None,
             }),
             for_powershell: None,
-            version: None,
-            original_name: None,
+            ..Default::default()
         };
 
-        let context = Context {
-            os: OperatingSystem::Windows,
-            shell: Shell::Cmd,
-            config_path: OsString::new(),
-        };
+        let context = cmd_context();
 
         env.fold(&context);
 
@@ -653,21 +1689,21 @@ mod tests {
         // Maps should be merged
         assert_eq!(
             env.global.set,
-            Some(HashMap::from([
+            Some(IndexMap::from([
                 ("GLOBAL_KEY".to_string(), "cmd_override".to_string()),
                 ("CMD_KEY".to_string(), "cmd_value".to_string()),
             ]))
         );
         assert_eq!(
             env.global.append,
-            Some(HashMap::from([(
+            Some(IndexMap::from([(
                 "GLOBAL_APPEND".to_string(),
                 "global_append".to_string()
             )]))
         );
         assert_eq!(
             env.global.prepend,
-            Some(HashMap::from([(
+            Some(IndexMap::from([(
                 "CMD_PREPEND".to_string(),
                 "cmd_prepend".to_string()
             )]))
@@ -680,7 +1716,7 @@ mod tests {
         );
         assert_eq!(
             env.global.reuse,
-            Some(vec!["global_reuse".to_string(), "cmd_reuse".to_string()])
+            Some(vec!["global_reuse".into(), "cmd_reuse".into()])
         );
 
         // Shell-specific properties should be consumed
@@ -707,23 +1743,808 @@ envC:
     VAR_C: "C"
 "#;
 
-        // Create a context
+        // Read environments from the config file
+        let config = config(yaml);
+
+        // Resolve dependencies for envA
+        let resolved = config
+            .resolve_dependencies("envA", &Settings::default())
+            .unwrap();
+
+        // Should contain envA and envB
+        let names: Vec<_> = resolved.iter().map(|e| e.name.as_str()).collect();
+        assert_eq!(names, vec!["envB", "envA"]);
+    }
+
+    #[test]
+    fn test_sort_environments_by_version() {
+        let pattern_env = |version: &str, version_sort: VersionSort| Environment {
+            name: format!("qt{}", version),
+            version: Some(version.to_string()),
+            original_name: Some("qt{}".to_string()),
+            version_sort,
+            ..Default::default()
+        };
+
+        let mut envs = vec![
+            pattern_env("6.9", VersionSort::Semver),
+            pattern_env("6.10", VersionSort::Semver),
+            pattern_env("6.10.1", VersionSort::Semver),
+        ];
+        Config::sort_environments(&mut envs);
+        let names: Vec<_> = envs.iter().map(|e| e.name.as_str()).collect();
+        assert_eq!(names, vec!["qt6.10.1", "qt6.10", "qt6.9"]);
+
+        let mut envs = vec![
+            pattern_env("6.10", VersionSort::Lexical),
+            pattern_env("6.9", VersionSort::Lexical),
+        ];
+        Config::sort_environments(&mut envs);
+        let names: Vec<_> = envs.iter().map(|e| e.name.as_str()).collect();
+        assert_eq!(names, vec!["qt6.9", "qt6.10"]);
+    }
+
+    #[test]
+    fn test_create_pattern_envs_with_version_from() {
+        let root = std::env::temp_dir().join(format!("use-version-from-{}", std::process::id()));
+        for (dir, release) in [
+            ("jdk-current", "JAVA_VERSION=\"21.0.2\"\n"),
+            ("jdk-old", "JAVA_VERSION=\"17.0.10\"\n"),
+            ("jdk-broken", "nothing here\n"),
+        ] {
+            fs::create_dir_all(root.join(dir)).unwrap();
+            fs::write(root.join(dir).join("release"), release).unwrap();
+        }
+
+        let env = Environment {
+            name: "jdk-{}".to_string(),
+            pattern: Some(Pattern {
+                path: root.to_string_lossy().to_string(),
+                regex: "^jdk-(.*)$".to_string(),
+                version_from: Some(VersionFrom {
+                    file: "release".to_string(),
+                    regex: Some("JAVA_VERSION=\"(.*)\"".to_string()),
+                }),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+
+        let mut envs = env.create_pattern_envs();
+        Config::sort_environments(&mut envs);
+        fs::remove_dir_all(&root).unwrap();
+
+        let versions: Vec<_> = envs
+            .iter()
+            .map(|e| (e.name.as_str(), e.version.as_deref().unwrap()))
+            .collect();
+        assert_eq!(
+            versions,
+            vec![
+                ("jdk-current", "21.0.2"),
+                ("jdk-old", "17.0.10"),
+                ("jdk-broken", "broken"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_find_environment_with_default_and_prefer() {
+        let qt = |version: &str, prefer: &str| Environment {
+            name: format!("qt{}", version),
+            version: Some(version.to_string()),
+            original_name: Some("qt{}".to_string()),
+            prefer: Prefer::try_from(prefer.to_string()).unwrap(),
+            ..Default::default()
+        };
+        let msvc = |name: &str, default: bool| Environment {
+            name: name.to_string(),
+            default,
+            ..Default::default()
+        };
+
+        let config = |prefer: &str| {
+            let mut environments = vec![
+                qt("6.9.1", prefer),
+                qt("6.8.3", prefer),
+                qt("6.8.2", prefer),
+                qt("5.15.2", prefer),
+                msvc("msvc2019", true),
+                msvc("msvc2022", false),
+            ];
+            Config::sort_environments(&mut environments);
+            Config { environments }
+        };
+        let find =
+            |config: &Config, name: &str| config.find_environment(name).unwrap().name.clone();
+
+        let newest = config("newest");
+        assert_eq!(find(&newest, "qt"), "qt6.9.1");
+        assert_eq!(find(&newest, "qt6.8"), "qt6.8.3");
+        assert_eq!(find(&newest, "msvc"), "msvc2019");
+        assert_eq!(find(&newest, "msvc2022"), "msvc2022");
+
+        let oldest = config("oldest");
+        assert_eq!(find(&oldest, "qt"), "qt5.15.2");
+        assert_eq!(find(&oldest, "qt6"), "qt6.8.2");
+
+        let lts = config("~6.8");
+        assert_eq!(find(&lts, "qt"), "qt6.8.3");
+        assert_eq!(find(&lts, "qt6.9"), "qt6.9.1");
+        assert_eq!(find(&lts, "qt5"), "qt5.15.2");
+        assert_eq!(find(&lts, "qt6.8.2"), "qt6.8.2");
+    }
+
+    #[test]
+    fn test_find_environment_with_default_pattern() {
+        let root = std::env::temp_dir().join(format!("use-default-pattern-{}", std::process::id()));
+        for dir in ["6.5.0", "6.8.0"] {
+            fs::create_dir_all(root.join(dir)).unwrap();
+        }
+        let yaml = r#"
+qt{}:
+  default: true
+  pattern:
+    path: 'ROOT'
+    regex: "^(\\d.*)$"
+    prefer: oldest
+qtcreator:
+  display: Qt Creator
+"#
+        .replace("ROOT", &root.to_string_lossy().replace('\\', "/"));
+
+        let config = config(&yaml);
+        fs::remove_dir_all(&root).unwrap();
+
+        // The preferred version is chosen among the default environments
+        assert_eq!(config.find_environment("qt").unwrap().name, "qt6.5.0");
+        assert_eq!(config.find_environment("qt6.8").unwrap().name, "qt6.8.0");
+    }
+
+    #[test]
+    fn test_matrix_expansion_from_yaml() {
+        let yaml = r#"
+msvc2022:
+  display: "MSVC 2022 - {arch} - {config}"
+  matrix:
+    arch: [x64, arm64]
+    config: [debug, release]
+  set:
+    BUILD_ARCH: "{arch}"
+    BUILD_TYPE: "{config}"
+tools-{arch}:
+  matrix:
+    arch: [x64, x86]
+  use:
+    - msvc2022-{arch}-release
+"#;
+
+        let config = config(yaml);
+
+        assert_eq!(
+            config.list(),
+            vec![
+                "msvc2022-arm64-debug",
+                "msvc2022-arm64-release",
+                "msvc2022-x64-debug",
+                "msvc2022-x64-release",
+                "tools-x64",
+                "tools-x86",
+            ]
+        );
+
+        let resolved = config
+            .resolve_dependencies("tools-x64", &Settings::default())
+            .unwrap();
+        let names: Vec<_> = resolved.iter().map(|e| e.name.as_str()).collect();
+        assert_eq!(names, vec!["msvc2022-x64-release", "tools-x64"]);
+        assert_eq!(resolved[0].display_name(), "MSVC 2022 - x64 - release");
+        assert_eq!(
+            resolved[0].global.set,
+            Some(IndexMap::from([
+                ("BUILD_ARCH".to_string(), "x64".to_string()),
+                ("BUILD_TYPE".to_string(), "release".to_string()),
+            ]))
+        );
+    }
+
+    #[test]
+    fn test_matrix_with_pattern_from_yaml() {
+        let root = std::env::temp_dir().join(format!("use-matrix-pattern-{}", std::process::id()));
+        for dir in ["x64/6.8.0", "x64/6.9.1", "arm64/6.9.1"] {
+            fs::create_dir_all(root.join(dir)).unwrap();
+        }
+        let root_path = root.to_string_lossy().replace('\\', "/");
+        let yaml = r#"
+qt{}-{arch}:
+  matrix:
+    arch: [x64, arm64]
+  pattern:
+    path: 'ROOT/{arch}'
+    regex: "^(\\d.*)$"
+  provides: ['qt-{arch}']
+  conflicts: ['qt*-{arch}']
+  requires:
+    - file: 'ROOT/{arch}/{}'
+  set:
+    QTDIR: 'ROOT/{arch}/{}'
+"#
+        .replace("ROOT", &root_path);
+
+        let config = config(&yaml);
+        fs::remove_dir_all(&root).unwrap();
+
+        let mut names = config.list();
+        names.sort();
+        assert_eq!(names, vec!["qt6.8.0-x64", "qt6.9.1-arm64", "qt6.9.1-x64"]);
+
+        let env = config.find_environment("qt6.9.1-arm64").unwrap();
+        assert_eq!(env.version.as_deref(), Some("6.9.1"));
+        assert_eq!(env.provides, Some(vec!["qt-arm64".to_string()]));
+        assert_eq!(env.conflicts, Some(vec!["qt*-arm64".to_string()]));
+        assert_eq!(
+            env.requires,
+            Some(vec![Requirement::File(format!(
+                "{}/arm64/6.9.1",
+                root_path
+            ))])
+        );
+        assert_eq!(
+            env.global.set,
+            Some(IndexMap::from([(
+                "QTDIR".to_string(),
+                format!("{}/arm64/6.9.1", root_path)
+            )]))
+        );
+        assert!(env.conflicts_with("qt6.8.0-arm64"));
+        assert!(!env.conflicts_with("qt6.8.0-x64"));
+    }
+
+    #[test]
+    fn test_extends_from_yaml() {
+        let yaml = r#"
+base:
+  abstract: true
+  display: "Base"
+  script: "echo base"
+  set:
+    VAR_A: "base"
+    VAR_B: "base"
+  separator:
+    TAGS: ","
+  path:
+    - base/path
+project:
+  extends: base
+  set:
+    VAR_B: "project"
+  path:
+    - project/path
+subproject:
+  extends: project
+  display: "Sub project"
+"#;
+
+        let config = config(yaml);
+
+        // Abstract environments are not listed
+        assert_eq!(config.list(), vec!["project", "subproject"]);
+
+        let env = config.find_environment("subproject").unwrap();
+        assert_eq!(env.display_name(), "Sub project");
+        assert_eq!(env.global.script, Some("echo base".to_string()));
+        assert_eq!(
+            env.global.set,
+            Some(IndexMap::from([
+                ("VAR_A".to_string(), "base".to_string()),
+                ("VAR_B".to_string(), "project".to_string()),
+            ]))
+        );
+        assert_eq!(
+            env.global.path,
+            Some(vec!["base/path".to_string(), "project/path".to_string()])
+        );
+        assert_eq!(env.separator("TAGS"), Some(","));
+        assert_eq!(env.separator("PATH"), None);
+    }
+
+    #[test]
+    fn test_paths_from_yaml() {
+        let yaml = r#"
+base:
+  abstract: true
+  paths:
+    LD_LIBRARY_PATH: [/opt/base/lib]
+qt:
+  extends: base
+  paths:
+    LD_LIBRARY_PATH:
+      - /opt/qt/{}/lib
+    CMAKE_PREFIX_PATH:
+      append: ["/opt/qt/{}"]
+      prepend: [/opt/cmake]
+"#;
         let context = Context {
-            os: OperatingSystem::Windows,
-            shell: Shell::Cmd,
+            os: OperatingSystem::Linux,
+            shell: Shell::Powershell,
             config_path: OsString::new(),
         };
+        let source = SourceFile {
+            path: PathBuf::from("use.yaml"),
+            content: yaml.to_string(),
+        };
+        let config_file: ConfigFile = yaml_serde::from_str(yaml).unwrap();
+        let mut env_hash =
+            Config::prepare_envs(&context, &source, config_file.environments).unwrap();
+        let mut env = env_hash.remove("qt").unwrap();
+        env.replace_placeholders("6.8");
+
+        let paths = env.global.paths.as_ref().unwrap();
+        let keys: Vec<_> = paths.keys().map(String::as_str).collect();
+        assert_eq!(keys, vec!["LD_LIBRARY_PATH", "CMAKE_PREFIX_PATH"]);
+        assert_eq!(
+            paths["LD_LIBRARY_PATH"],
+            PathList {
+                prepend: vec!["/opt/base/lib".to_string(), "/opt/qt/6.8/lib".to_string()],
+                append: Vec::new(),
+            }
+        );
+        assert_eq!(
+            paths["CMAKE_PREFIX_PATH"],
+            PathList {
+                prepend: vec!["/opt/cmake".to_string()],
+                append: vec!["/opt/qt/6.8".to_string()],
+            }
+        );
 
-        // Read environments from the config file
+        let invalid = "tool:\n  paths:\n    PYTHONPATH:\n      apend: [/opt/py]\n";
+        assert!(Config::read_config_from_string(invalid, &context).is_err());
+    }
+
+    #[test]
+    fn test_extends_errors() {
+        let context = cmd_context();
+
+        let unknown = "envA:\n  extends: envB\n";
+        let error = Config::read_config_from_string(unknown, &context).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Environment envA extends unknown environment envB\n \
+             --> use.yaml:1:1\n  \
+             |\n\
+             1 | envA:\n  \
+             | ^"
+        );
+
+        let circular = "envA:\n  extends: envB\nenvB:\n  extends: envA\n";
+        let error = Config::read_config_from_string(circular, &context).unwrap_err();
+        assert!(error.to_string().starts_with("Circular extends"));
+    }
+
+    #[test]
+    fn test_show_effective_config() {
+        let yaml = r#"
+base:
+  abstract: true
+  set:
+    VAR_A: "base"
+tool:
+  extends: base
+  use:
+    - name: unix
+      for: linux
+  for_cmd:
+    set:
+      VAR_B: "cmd"
+  for_powershell:
+    set:
+      VAR_B: "powershell"
+"#;
+        let config = config(yaml);
+
+        let value = config.show(None, &Settings::default()).unwrap();
+        assert_eq!(
+            value,
+            serde_json::json!({
+                "tool": {
+                    "set": { "VAR_A": "base", "VAR_B": "cmd" }
+                }
+            })
+        );
+        let error = config.show(Some("base"), &Settings::default()).unwrap_err();
+        assert_eq!(error.to_string(), "Environment base not found");
+    }
+
+    #[test]
+    fn test_settings_section() {
+        let context = cmd_context();
+
+        let yaml = "settings:\n  update_title: false\n  color: never\nenvA:\n  display: A\n";
+        assert_eq!(config(yaml).list(), vec!["envA"]);
+
+        let invalid = "settings:\n  prompt_var: my-prompt\nenvA:\n  display: A\n";
+        let error = Config::read_config_from_string(invalid, &context).unwrap_err();
+        assert!(
+            error
+                .to_string()
+                .starts_with("Invalid environment variable name 'my-prompt' for prompt-var"),
+            "{}",
+            error
+        );
+
+        let schema: serde_json::Value = serde_json::from_str(&Config::schema()).unwrap();
+        assert_eq!(
+            schema["properties"]["settings"]["$ref"],
+            "#/$defs/FileSettings"
+        );
+    }
+
+    #[test]
+    fn test_title() {
+        let root = std::env::temp_dir().join(format!("use-title-{}", std::process::id()));
+        fs::create_dir_all(root.join("6.8.2")).unwrap();
+        let yaml = r#"
+base:
+  abstract: true
+  title: "{display} [{version}]"
+qt{}:
+  extends: base
+  display: Qt {}
+  pattern:
+    path: 'ROOT'
+    regex: "^(\\d.*)$"
+tool:
+  extends: base
+  display: Tool
+quiet:
+  update_title: false
+  for_powershell:
+    update_title: true
+plain: {}
+"#
+        .replace("ROOT", &root.to_string_lossy().replace('\\', "/"));
+        let config = config(&yaml);
+        fs::remove_dir_all(&root).unwrap();
+        let settings = Settings::default();
+
+        let qt = config.find_environment("qt").unwrap();
+        assert_eq!(qt.title(&settings).as_deref(), Some("Qt 6.8.2 [6.8.2]"));
+        let plain = config.find_environment("plain").unwrap();
+        assert_eq!(plain.title(&settings).as_deref(), Some("plain"));
+        // No empty version for the environments without one
+        let unversioned = config.find_environment("tool").unwrap();
+        assert_eq!(unversioned.title(&settings).as_deref(), Some("Tool"));
+        for (template, expected) in [
+            ("{name} ({version})", "tool"),
+            ("{name} {version} - {display}", "tool - Tool"),
+            ("{version}{name}", "tool"),
+        ] {
+            let settings = Settings {
+                title: template.to_string(),
+                ..Settings::default()
+            };
+            let env = Environment {
+                name: "tool".to_string(),
+                global: CommonProperties {
+                    display: Some("Tool".to_string()),
+                    ..Default::default()
+                },
+                ..Default::default()
+            };
+            assert_eq!(env.title(&settings).as_deref(), Some(expected));
+        }
+        let quiet = config.find_environment("quiet").unwrap();
+        assert_eq!(quiet.title(&settings), None);
+
+        let settings = Settings {
+            update_title: false,
+            ..Settings::default()
+        };
+        assert_eq!(qt.title(&settings), None);
+    }
+
+    #[test]
+    fn test_located_errors() {
+        let context = cmd_context();
+
+        let wrong_type = "qt:\n  display: Qt\n  path: C:\\Qt\\bin\n";
+        let error = Config::read_config_from_string(wrong_type, &context).unwrap_err();
+        let error = error.to_string();
+        assert!(error.starts_with("qt: invalid type: string"), "{}", error);
+        assert!(error.contains(" --> use.yaml:3:9\n"), "{}", error);
+        assert!(
+            error.ends_with("3 |   path: C:\\Qt\\bin\n  |         ^"),
+            "{}",
+            error
+        );
+
+        let bad_regex = "qt{}:\n  pattern:\n    path: C:\\Qt\n    regex: '(\\d+'\n";
+        let error = Config::read_config_from_string(bad_regex, &context).unwrap_err();
+        let error = error.to_string();
+        assert!(error.contains(" --> use.yaml:4:12\n"), "{}", error);
+    }
+
+    #[test]
+    fn test_resolve_rich_dependencies_from_yaml() {
+        let root = std::env::temp_dir().join(format!("use-rich-{}", std::process::id()));
+        for dir in ["6.9.1", "6.8.2", "6.5.3", "5.15.2"] {
+            fs::create_dir_all(root.join(dir)).unwrap();
+        }
+        let yaml = r#"
+project:
+  use:
+    - name: ccache
+      optional: true
+    - name: msvc2022
+      for: windows
+    - name: gcc
+      for: linux
+    - name: qt
+      version: ">=6.5, <6.9"
+    - cmake
+cmake:
+  set:
+    CMAKE_GENERATOR: Ninja
+msvc2022:
+  display: MSVC
+gcc:
+  display: GCC
+qt{}:
+  pattern:
+    path: 'ROOT'
+    regex: "^(\\d.*)$"
+  set:
+    QT_VERSION: "{}"
+"#
+        .replace("ROOT", &root.to_string_lossy().replace('\\', "/"));
+
+        let context = cmd_context();
+        let config = config(&yaml);
+        fs::remove_dir_all(&root).unwrap();
+
+        let resolved = config
+            .resolve_dependencies("project", &Settings::default())
+            .unwrap();
+        let names: Vec<_> = resolved.iter().map(|e| e.name.as_str()).collect();
+        assert_eq!(names, vec!["msvc2022", "qt6.8.2", "cmake", "project"]);
+
+        let yaml = "project:\n  use:\n    - name: ccache\n";
         let envs = Config::read_config_from_string(yaml, &context).unwrap();
+        let error = Config { environments: envs }
+            .resolve_dependencies("project", &Settings::default())
+            .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Environment ccache not found, required by project"
+        );
+        assert_eq!(error.exit_code(), crate::error::EXIT_DEPENDENCY_NOT_FOUND);
+    }
 
-        let config = Config { environments: envs };
+    #[test]
+    fn test_version_constraint_with_pattern_from_yaml() {
+        let root = std::env::temp_dir().join(format!("use-constraint-{}", std::process::id()));
+        for dir in ["6.5.3", "6.8.1", "6.8.2", "6.9.1"] {
+            fs::create_dir_all(root.join(dir)).unwrap();
+        }
+        let yaml = r#"
+qt{}:
+  pattern:
+    path: 'ROOT'
+    regex: "^(\\d.*)$"
+qt7.0:
+  display: Not a pattern
+project:
+  use:
+    - name: qt
+      version: ">=6.5, <6.9"
+next:
+  use:
+    - name: qt
+      version: ">=7"
+"#
+        .replace("ROOT", &root.to_string_lossy().replace('\\', "/"));
+
+        let config = config(&yaml);
+        fs::remove_dir_all(&root).unwrap();
+
+        // The newest version matching the constraint
+        let resolved = config
+            .resolve_dependencies("project", &Settings::default())
+            .unwrap();
+        let names: Vec<_> = resolved.iter().map(|e| e.name.as_str()).collect();
+        assert_eq!(names, vec!["qt6.8.2", "project"]);
 
-        // Resolve dependencies for envA
-        let resolved = config.resolve_dependencies("envA").unwrap();
+        // Environments without a pattern have no version
+        let error = config
+            .resolve_dependencies("next", &Settings::default())
+            .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Environment qt >=7 not found, required by next"
+        );
+    }
 
-        // Should contain envA and envB
+    #[test]
+    fn test_resolve_provided_capabilities_from_yaml() {
+        let yaml = r#"
+project:
+  use:
+    - cxx-compiler
+msvc2019:
+  provides: [cxx-compiler]
+msvc2022:
+  provides: [cxx-compiler]
+  priority: 10
+clang-cl:
+  provides: [cxx-compiler]
+"#;
+
+        let config = config(yaml);
+
+        // Highest priority by default
+        let resolved = config
+            .resolve_dependencies("project", &Settings::default())
+            .unwrap();
         let names: Vec<_> = resolved.iter().map(|e| e.name.as_str()).collect();
-        assert_eq!(names, vec!["envB", "envA"]);
+        assert_eq!(names, vec!["msvc2022", "project"]);
+
+        // Provider chosen by the user
+        let settings = Settings {
+            providers: HashMap::from([("cxx-compiler".to_string(), "clang".to_string())]),
+            ..Default::default()
+        };
+        let resolved = config.resolve_dependencies("project", &settings).unwrap();
+        let names: Vec<_> = resolved.iter().map(|e| e.name.as_str()).collect();
+        assert_eq!(names, vec!["clang-cl", "project"]);
+
+        // Capabilities can be used directly
+        let resolved = config
+            .resolve_dependencies("cxx-compiler", &settings)
+            .unwrap();
+        assert_eq!(resolved.last().unwrap().name, "clang-cl");
+    }
+
+    #[test]
+    fn test_environment_named_like_a_capability() {
+        let yaml = r#"
+python:
+  display: Python
+conda:
+  provides: [python]
+project:
+  use: [python]
+"#;
+        let config = config(yaml);
+        let settings = Settings::default();
+
+        // An environment with the exact name wins over a provider
+        let resolved = config.resolve_dependencies("python", &settings).unwrap();
+        let names: Vec<_> = resolved.iter().map(|e| e.name.as_str()).collect();
+        assert_eq!(names, vec!["python"]);
+        let resolved = config.resolve_dependencies("project", &settings).unwrap();
+        let names: Vec<_> = resolved.iter().map(|e| e.name.as_str()).collect();
+        assert_eq!(names, vec!["python", "project"]);
+        let value = config.show(Some("python"), &settings).unwrap();
+        assert!(value.get("python").is_some());
+    }
+
+    #[test]
+    fn test_print_env_with_requirements() {
+        let root = std::env::temp_dir().join(format!("use-print-env-{}", std::process::id()));
+        fs::create_dir_all(root.join("bin")).unwrap();
+        let yaml = r#"
+tools:
+  set:
+    USE_TEST_TOOLS: 'ROOT'
+  path:
+    - ${USE_TEST_TOOLS}/bin
+project:
+  use: [tools]
+  requires:
+    - env: USE_TEST_TOOLS
+    - file: ${USE_TEST_TOOLS}/bin
+  set:
+    PROJECT: project
+broken:
+  use: [tools]
+  requires:
+    - env: USE_DOES_NOT_EXIST
+    - file: ${USE_TEST_TOOLS}/missing
+"#
+        .replace("ROOT", &root.to_string_lossy().replace('\\', "/"));
+        let config = config(&yaml);
+        let settings = Settings::default();
+
+        // Requirements met by a dependency
+        let printer = RecordingPrinter::default();
+        config.print_env("project", &settings, &printer).unwrap();
+        assert!(
+            printer
+                .lines
+                .borrow()
+                .contains(&"set PROJECT=project".to_string())
+        );
+
+        // Nothing is printed if a requirement is not met, not even the dependencies
+        let printer = RecordingPrinter::default();
+        let error = config.print_env("broken", &settings, &printer).unwrap_err();
+        fs::remove_dir_all(&root).unwrap();
+        assert!(matches!(error, Error::Requirements(_)));
+        assert_eq!(
+            error.to_string(),
+            format!(
+                "Environment broken cannot be used: environment variable USE_DOES_NOT_EXIST \
+                 not set, file {}/missing not found",
+                root.to_string_lossy().replace('\\', "/")
+            )
+        );
+        assert!(printer.lines.borrow().is_empty());
+    }
+
+    #[test]
+    fn test_check_conflicts_from_yaml() {
+        let yaml = r#"
+msvc2019:
+  conflicts: [msvc*]
+msvc2022:
+  conflicts: [msvc*]
+project:
+  use:
+    - msvc2019
+    - msvc2022
+python:
+  set:
+    PYTHON: "python"
+"#;
+
+        let config = config(yaml);
+        let settings = Settings::default();
+
+        // Conflict in the same dependency chain
+        let envs = config.resolve_dependencies("project", &settings).unwrap();
+        assert_eq!(
+            config.check_conflicts(&envs, &[]).unwrap_err().to_string(),
+            "Environment msvc2022 conflicts with msvc2019"
+        );
+
+        // Conflict with an environment already active
+        let envs = config.resolve_dependencies("msvc2022", &settings).unwrap();
+        assert_eq!(
+            config
+                .check_conflicts(&envs, &["msvc2019".to_string()])
+                .unwrap_err()
+                .to_string(),
+            "Environment msvc2022 conflicts with msvc2019, which is already active"
+        );
+        assert!(
+            config
+                .check_conflicts(&envs, &["msvc2022".to_string(), "python".to_string()])
+                .is_ok()
+        );
+    }
+
+    #[test]
+    fn test_schema() {
+        let schema: serde_json::Value = serde_json::from_str(&Config::schema()).unwrap();
+        let environment = &schema["$defs"]["Environment"]["properties"];
+        for key in [
+            "display",
+            "use",
+            "set",
+            "append",
+            "prepend",
+            "path",
+            "alias",
+            "script",
+            "go",
+            "for",
+            "pattern",
+            "for_cmd",
+            "for_powershell",
+            "for_pwsh",
+        ] {
+            assert!(environment.get(key).is_some(), "missing {} in schema", key);
+        }
+        assert!(environment.get("version").is_none());
     }
 }
@@ -0,0 +1,256 @@
+use std::path::Path;
+use which::which;
+
+use super::{Config, SETTINGS_KEY, collect_variables, expand_variables};
+use crate::context::Context;
+use crate::error::Error;
+use crate::settings::{Settings, check_title};
+
+/// Severity of a problem found by the doctor
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    Info,
+    Warning,
+    Error,
+}
+
+/// Problem found by the doctor for an environment
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    pub env: String,
+    pub severity: Severity,
+    pub message: String,
+}
+
+impl Config {
+    /// Check the configuration against the state of the machine, and return all
+    /// problems found, sorted by environment name
+    pub fn doctor(context: &Context, settings: &Settings) -> Result<Vec<Diagnostic>, Error> {
+        let path = Path::new(&context.config_path);
+        if !path.exists() {
+            return Err(Error::ConfigNotFound(path.to_path_buf()));
+        }
+        let (source, env_hash) = Self::read_env_hash(path)?;
+        let environments = Self::create_env_vector(context, &source, env_hash.clone())?;
+        let config = Config { environments };
+
+        let mut diagnostics = Vec::new();
+        let mut report = |env: &str, severity: Severity, message: String| {
+            diagnostics.push(Diagnostic {
+                env: env.to_string(),
+                severity,
+                message,
+            })
+        };
+
+        // Definitions: filtered environments and patterns
+        let env_hash = Self::prepare_envs(context, &source, env_hash)?;
+        for env in env_hash.into_values().filter(|env| !env.is_abstract) {
+            if let Some(c) = env.context.as_ref().filter(|c| !context.check(c)) {
+                report(
+                    &env.name,
+                    Severity::Info,
+                    format!("not available on this machine (for: {})", c),
+                );
+                continue;
+            }
+            for env in env.create_matrix_envs() {
+                if let Some(pattern) = &env.pattern {
+                    if !Path::new(&pattern.path).is_dir() {
+                        report(
+                            &env.name,
+                            Severity::Warning,
+                            format!("pattern path {} does not exist", pattern.path),
+                        );
+                    } else if env.create_pattern_envs().is_empty() {
+                        report(
+                            &env.name,
+                            Severity::Warning,
+                            format!(
+                                "pattern {} matches nothing in {}",
+                                pattern.regex, pattern.path
+                            ),
+                        );
+                    }
+                }
+            }
+        }
+
+        // Effective environments
+        for env in &config.environments {
+            let envs = match config.resolve_dependencies(&env.name, settings) {
+                Ok(envs) => envs,
+                Err(e) => {
+                    report(&env.name, Severity::Error, e.to_string());
+                    continue;
+                }
+            };
+            // The requirements can be met by the dependencies, set up before
+            let dependencies = collect_variables(&envs[..envs.len() - 1]);
+            if let Err(e) = env.check_requirements(&dependencies) {
+                report(&env.name, Severity::Warning, e.to_string());
+            }
+            if let Some(title) = &env.global.title
+                && let Err(e) = check_title(title)
+            {
+                report(&env.name, Severity::Warning, e);
+            }
+
+            let variables = collect_variables(&envs);
+            let lists = env.global.paths.iter().flat_map(|paths| paths.values());
+            let list_paths = lists.flat_map(|list| list.prepend.iter().chain(&list.append));
+            for path in env.global.path.iter().flatten().chain(list_paths) {
+                if let Some(path) = expand_variables(path, &variables)
+                    && !Path::new(&path).exists()
+                {
+                    report(
+                        &env.name,
+                        Severity::Warning,
+                        format!("path {} does not exist", path),
+                    );
+                }
+            }
+            if let Some(go) = &env.global.go
+                && let Some(go) = expand_variables(go, &variables)
+                && !Path::new(&go).is_dir()
+            {
+                report(
+                    &env.name,
+                    Severity::Warning,
+                    format!("go directory {} does not exist", go),
+                );
+            }
+            for (name, command) in env.global.alias.iter().flatten() {
+                if let Some(command) = expand_variables(command, &variables)
+                    && !is_executable(&command)
+                {
+                    report(
+                        &env.name,
+                        Severity::Warning,
+                        format!("alias {} target {} is not an executable", name, command),
+                    );
+                }
+            }
+        }
+
+        // Settings referring to environments
+        if let Some(default_env) = &settings.default_env
+            && config.find_environment(default_env).is_none()
+        {
+            report(
+                SETTINGS_KEY,
+                Severity::Warning,
+                format!("default environment {} not found", default_env),
+            );
+        }
+
+        diagnostics.sort_by(|a, b| a.env.cmp(&b.env));
+        Ok(diagnostics)
+    }
+}
+
+/// Check if the command is an executable, either a path or a command in the PATH
+fn is_executable(command: &str) -> bool {
+    let path = Path::new(command);
+    if path.components().count() > 1 {
+        path.is_file()
+    } else {
+        which(command).is_ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Shell;
+    use crate::context::OperatingSystem;
+    use std::fs;
+
+    #[test]
+    fn test_doctor() {
+        let root = std::env::temp_dir().join(format!("use-doctor-{}", std::process::id()));
+        fs::create_dir_all(root.join("tools").join("bin")).unwrap();
+        let config_path = root.join("use.yaml");
+        let yaml = r#"
+tools:
+  set:
+    TOOLS_DIR: "{root}/tools"
+  path:
+    - ${TOOLS_DIR}/bin
+    - ${TOOLS_DIR}/missing
+  go: "{root}/nowhere"
+  alias:
+    tool: "{root}/tools/bin/tool.exe"
+mac:
+  for: macos
+qt{}:
+  pattern:
+    path: "{root}/tools"
+    regex: "^(\\d+)$"
+broken:
+  use:
+    - unknown
+"#
+        .replace("{root}", &root.to_string_lossy());
+        fs::write(&config_path, yaml).unwrap();
+
+        let context = Context {
+            os: OperatingSystem::Linux,
+            shell: Shell::Powershell,
+            config_path: config_path.into(),
+        };
+        let settings = Settings {
+            default_env: Some("missing".to_string()),
+            ..Settings::default()
+        };
+        let diagnostics = Config::doctor(&context, &settings);
+        fs::remove_dir_all(&root).unwrap();
+
+        let root = root.to_string_lossy();
+        let diagnostics: Vec<_> = diagnostics
+            .unwrap()
+            .into_iter()
+            .map(|d| (d.env, d.severity, d.message.replace(root.as_ref(), "")))
+            .collect();
+        assert_eq!(
+            diagnostics,
+            vec![
+                (
+                    "broken".to_string(),
+                    Severity::Error,
+                    "Environment unknown not found, required by broken".to_string()
+                ),
+                (
+                    "mac".to_string(),
+                    Severity::Info,
+                    "not available on this machine (for: macos)".to_string()
+                ),
+                (
+                    "qt{}".to_string(),
+                    Severity::Warning,
+                    "pattern ^(\\d+)$ matches nothing in /tools".to_string()
+                ),
+                (
+                    "settings".to_string(),
+                    Severity::Warning,
+                    "default environment missing not found".to_string()
+                ),
+                (
+                    "tools".to_string(),
+                    Severity::Warning,
+                    "path /tools/missing does not exist".to_string()
+                ),
+                (
+                    "tools".to_string(),
+                    Severity::Warning,
+                    "go directory /nowhere does not exist".to_string()
+                ),
+                (
+                    "tools".to_string(),
+                    Severity::Warning,
+                    "alias tool target /tools/bin/tool.exe is not an executable".to_string()
+                ),
+            ]
+        );
+    }
+}
@@ -0,0 +1,432 @@
+use regex::Regex;
+use std::io;
+use std::path::Path;
+use std::sync::LazyLock;
+
+use super::{Config, ConfigFile, ConfigFormat, SETTINGS_KEY};
+use crate::context::Context;
+use crate::error::{Error, SourceFile};
+
+static KEY_REGEX: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r#"^(\s*)(["']?)([^\s"':#-][^"':#]*)(["']?)\s*:(\s|$)"#).unwrap());
+
+/// Return the indentation and the key of a YAML line defining a mapping key
+fn yaml_key(line: &str) -> Option<(usize, &str)> {
+    KEY_REGEX.captures(line).map(|caps| {
+        let key = caps.get(3).unwrap();
+        (caps[1].len(), line[key.range()].trim_end())
+    })
+}
+
+/// Change made to the configuration file by the `use config` commands
+#[derive(Debug, Clone, PartialEq)]
+pub enum ConfigEdit {
+    /// Add a new environment
+    Add { name: String, display: String },
+    /// Set an environment variable in an environment
+    SetVar {
+        name: String,
+        key: String,
+        value: String,
+    },
+    /// Add a directory to the `path` of an environment
+    AddPath { name: String, dir: String },
+    /// Remove an environment
+    Remove { name: String },
+}
+
+impl Config {
+    /// Apply the change to the configuration file, keeping its comments and layout.
+    /// Only YAML files can be edited.
+    pub fn edit(context: &Context, edit: &ConfigEdit) -> Result<(), Error> {
+        let path = Path::new(&context.config_path);
+        if !path.exists() {
+            return Err(Error::ConfigNotFound(path.to_path_buf()));
+        }
+        if ConfigFormat::from_path(path) != ConfigFormat::Yaml {
+            return Err(Error::ConfigWrite(
+                path.to_path_buf(),
+                io::Error::new(
+                    io::ErrorKind::Unsupported,
+                    "only YAML configuration files can be edited",
+                ),
+            ));
+        }
+
+        let source = SourceFile::read(path)?;
+        let content = edit_content(&source, edit)?;
+
+        // Check the result before writing it
+        let edited = SourceFile {
+            path: path.to_path_buf(),
+            content,
+        };
+        ConfigFormat::Yaml.parse::<ConfigFile>(&edited)?;
+        std::fs::write(path, edited.content).map_err(|e| Error::ConfigWrite(path.to_path_buf(), e))
+    }
+}
+
+impl Config {
+    /// Return the line (1-based) where the environment is defined in the configuration file
+    pub fn find_definition(context: &Context, name: &str) -> Result<usize, Error> {
+        let path = Path::new(&context.config_path);
+        if !path.exists() {
+            return Err(Error::ConfigNotFound(path.to_path_buf()));
+        }
+        SourceFile::read(path)?
+            .find_key(name)
+            .ok_or_else(|| Error::EnvironmentNotFound(name.to_string()))
+    }
+}
+
+/// Lines of a YAML document, with helpers to find the block of a key
+struct YamlLines {
+    lines: Vec<String>,
+    indent: usize,
+}
+
+impl YamlLines {
+    fn new(content: &str) -> Self {
+        let lines: Vec<String> = content.lines().map(String::from).collect();
+        // Indentation used by the file, 2 spaces by default
+        let indent = lines
+            .iter()
+            .filter(|line| is_content(line))
+            .map(|line| indent_of(line))
+            .find(|indent| *indent > 0)
+            .unwrap_or(2);
+        Self { lines, indent }
+    }
+
+    /// Find the key with the given indentation in the range, and return the range of its
+    /// block: the key line and its content, without the trailing empty lines or comments
+    fn find_block(
+        &self,
+        key: &str,
+        indent: usize,
+        start: usize,
+        end: usize,
+    ) -> Option<(usize, usize)> {
+        let line = (start..end).find(|&i| yaml_key(&self.lines[i]) == Some((indent, key)))?;
+        let mut block_end = line + 1;
+        for i in line + 1..end {
+            let text = &self.lines[i];
+            if !is_content(text) {
+                continue;
+            }
+            let is_item = text.trim_start().starts_with('-');
+            if indent_of(text) > indent || (indent_of(text) == indent && is_item) {
+                block_end = i + 1;
+            } else {
+                break;
+            }
+        }
+        Some((line, block_end))
+    }
+
+    /// Indentation of the content of a block, or the default one if the block is empty
+    fn child_indent(&self, (start, end): (usize, usize)) -> usize {
+        self.lines[start + 1..end]
+            .iter()
+            .find(|line| is_content(line))
+            .map(|line| indent_of(line))
+            .unwrap_or(indent_of(&self.lines[start]) + self.indent)
+    }
+
+    fn find_env(&self, name: &str) -> Result<(usize, usize), Error> {
+        self.find_block(name, 0, 0, self.lines.len())
+            .ok_or_else(|| Error::EnvironmentNotFound(name.to_string()))
+    }
+
+    /// Return the block of the property of the environment, if it exists and is a block
+    fn find_property(
+        &self,
+        source: &SourceFile,
+        env: (usize, usize),
+        name: &str,
+    ) -> Result<Option<(usize, usize)>, Error> {
+        let indent = self.child_indent(env);
+        let Some(block) = self.find_block(name, indent, env.0 + 1, env.1) else {
+            return Ok(None);
+        };
+        let line = &self.lines[block.0];
+        let value = line.split_once(':').map_or("", |(_, value)| value.trim());
+        if !value.is_empty() && !value.starts_with('#') {
+            let message = format!("{} is not a block, it can only be edited manually", name);
+            return Err(source.error_at(&message, block.0 + 1, indent + 1));
+        }
+        Ok(Some(block))
+    }
+
+    fn into_content(self, newline: &str, trailing: bool) -> String {
+        let mut content = self.lines.join(newline);
+        if trailing {
+            content.push_str(newline);
+        }
+        content
+    }
+}
+
+/// Apply the change to the YAML content
+fn edit_content(source: &SourceFile, edit: &ConfigEdit) -> Result<String, Error> {
+    let content = &source.content;
+    let newline = if content.contains("\r\n") {
+        "\r\n"
+    } else {
+        "\n"
+    };
+    let mut yaml = YamlLines::new(content);
+    let indent = " ".repeat(yaml.indent);
+
+    match edit {
+        ConfigEdit::Add { name, display } => {
+            if name == SETTINGS_KEY {
+                return Err(source.error(&format!(
+                    "{} is reserved for the settings section, and can't be an environment",
+                    name
+                )));
+            }
+            if yaml.find_env(name).is_ok() {
+                return Err(Error::EnvironmentExists(name.clone()));
+            }
+            if yaml
+                .lines
+                .last()
+                .is_some_and(|line| !line.trim().is_empty())
+            {
+                yaml.lines.push(String::new());
+            }
+            yaml.lines.push(format!("{}:", yaml_scalar(name)));
+            yaml.lines
+                .push(format!("{}display: {}", indent, yaml_scalar(display)));
+        }
+        ConfigEdit::SetVar { name, key, value } => {
+            let env = yaml.find_env(name)?;
+            match yaml.find_property(source, env, "set")? {
+                Some(set) => {
+                    let child_indent = yaml.child_indent(set);
+                    let line = format!(
+                        "{}{}: {}",
+                        " ".repeat(child_indent),
+                        yaml_scalar(key),
+                        yaml_scalar(value)
+                    );
+                    match yaml.find_block(key, child_indent, set.0 + 1, set.1) {
+                        Some((start, end)) => {
+                            yaml.lines.splice(start..end, [line]);
+                        }
+                        None => yaml.lines.insert(set.1, line),
+                    }
+                }
+                None => {
+                    let property_indent = " ".repeat(yaml.child_indent(env));
+                    yaml.lines.insert(env.1, format!("{}set:", property_indent));
+                    yaml.lines.insert(
+                        env.1 + 1,
+                        format!(
+                            "{}{}{}: {}",
+                            property_indent,
+                            indent,
+                            yaml_scalar(key),
+                            yaml_scalar(value)
+                        ),
+                    );
+                }
+            }
+        }
+        ConfigEdit::AddPath { name, dir } => {
+            let env = yaml.find_env(name)?;
+            match yaml.find_property(source, env, "path")? {
+                Some(path) => {
+                    let child_indent = yaml.child_indent(path);
+                    let item = format!("{}- {}", " ".repeat(child_indent), yaml_scalar(dir));
+                    if !yaml.lines[path.0 + 1..path.1].contains(&item) {
+                        yaml.lines.insert(path.1, item);
+                    }
+                }
+                None => {
+                    let property_indent = " ".repeat(yaml.child_indent(env));
+                    yaml.lines
+                        .insert(env.1, format!("{}path:", property_indent));
+                    yaml.lines.insert(
+                        env.1 + 1,
+                        format!("{}{}- {}", property_indent, indent, yaml_scalar(dir)),
+                    );
+                }
+            }
+        }
+        ConfigEdit::Remove { name } => {
+            let (mut start, mut end) = yaml.find_env(name)?;
+            // The comments just above the environment are part of it
+            while start > 0 && yaml.lines[start - 1].trim_start().starts_with('#') {
+                start -= 1;
+            }
+            // As well as the empty line separating it from the next one
+            if yaml
+                .lines
+                .get(end)
+                .is_some_and(|line| line.trim().is_empty())
+            {
+                end += 1;
+            } else if start > 0 && yaml.lines[start - 1].trim().is_empty() {
+                start -= 1;
+            }
+            yaml.lines.drain(start..end);
+        }
+    }
+
+    let trailing = content.ends_with('\n') || content.is_empty();
+    Ok(yaml.into_content(newline, trailing))
+}
+
+/// Format the string as a YAML scalar, quoting it only if needed
+fn yaml_scalar(value: &str) -> String {
+    let plain = yaml_serde::from_str::<serde_json::Value>(value).ok();
+    if plain == Some(serde_json::Value::String(value.to_string())) && !value.contains(": ") {
+        value.to_string()
+    } else {
+        format!("'{}'", value.replace('\'', "''"))
+    }
+}
+
+fn is_content(line: &str) -> bool {
+    let trimmed = line.trim_start();
+    !trimmed.is_empty() && !trimmed.starts_with('#')
+}
+
+fn indent_of(line: &str) -> usize {
+    line.len() - line.trim_start().len()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONFIG: &str = r#"# Build tools
+cmake:
+    display: CMake # comment
+    set:
+        CMAKE_GENERATOR: Ninja
+
+    path:
+    - C:\CMake\bin
+
+# Qt
+qt{}:
+    script: |
+        set:
+          NOT_A_KEY: 1
+"#;
+
+    fn source() -> SourceFile {
+        SourceFile {
+            path: "use.yaml".into(),
+            content: CONFIG.to_string(),
+        }
+    }
+
+    fn edit(edit: ConfigEdit) -> String {
+        edit_content(&source(), &edit).unwrap()
+    }
+
+    #[test]
+    fn test_edit_add_remove() {
+        let content = edit(ConfigEdit::Add {
+            name: "ninja".to_string(),
+            display: "Ninja: build".to_string(),
+        });
+        assert_eq!(
+            content,
+            CONFIG.to_string() + "\nninja:\n    display: 'Ninja: build'\n"
+        );
+        assert!(matches!(
+            edit_content(
+                &source(),
+                &ConfigEdit::Add {
+                    name: "cmake".to_string(),
+                    display: "CMake".to_string(),
+                }
+            ),
+            Err(Error::EnvironmentExists(_))
+        ));
+
+        let content = edit(ConfigEdit::Remove {
+            name: "cmake".to_string(),
+        });
+        assert_eq!(
+            content,
+            "# Qt\nqt{}:\n    script: |\n        set:\n          NOT_A_KEY: 1\n"
+        );
+        let content = edit(ConfigEdit::Remove {
+            name: "qt{}".to_string(),
+        });
+        assert_eq!(
+            content,
+            "# Build tools\ncmake:\n    display: CMake # comment\n    set:\n        CMAKE_GENERATOR: Ninja\n\n    path:\n    - C:\\CMake\\bin\n"
+        );
+    }
+
+    #[test]
+    fn test_edit_set_var() {
+        let content = edit(ConfigEdit::SetVar {
+            name: "cmake".to_string(),
+            key: "CMAKE_GENERATOR".to_string(),
+            value: "Unix Makefiles".to_string(),
+        });
+        assert!(content.contains("    set:\n        CMAKE_GENERATOR: Unix Makefiles\n\n"));
+
+        let content = edit(ConfigEdit::SetVar {
+            name: "cmake".to_string(),
+            key: "CMAKE_BUILD_TYPE".to_string(),
+            value: "#Debug".to_string(),
+        });
+        assert!(
+            content
+                .contains("        CMAKE_GENERATOR: Ninja\n        CMAKE_BUILD_TYPE: '#Debug'\n\n")
+        );
+
+        // The script content is not a property
+        let content = edit(ConfigEdit::SetVar {
+            name: "qt{}".to_string(),
+            key: "QTDIR".to_string(),
+            value: "C:\\Qt".to_string(),
+        });
+        assert!(content.ends_with("          NOT_A_KEY: 1\n    set:\n        QTDIR: C:\\Qt\n"));
+
+        assert!(matches!(
+            edit_content(
+                &source(),
+                &ConfigEdit::SetVar {
+                    name: "unknown".to_string(),
+                    key: "A".to_string(),
+                    value: "B".to_string(),
+                }
+            ),
+            Err(Error::EnvironmentNotFound(_))
+        ));
+    }
+
+    #[test]
+    fn test_edit_add_path() {
+        let content = edit(ConfigEdit::AddPath {
+            name: "cmake".to_string(),
+            dir: "C:\\CMake\\tools".to_string(),
+        });
+        assert!(
+            content.contains("    path:\n    - C:\\CMake\\bin\n    - C:\\CMake\\tools\n\n# Qt")
+        );
+
+        // Already there
+        let content = edit(ConfigEdit::AddPath {
+            name: "cmake".to_string(),
+            dir: "C:\\CMake\\bin".to_string(),
+        });
+        assert_eq!(content, CONFIG);
+
+        let content = edit(ConfigEdit::AddPath {
+            name: "qt{}".to_string(),
+            dir: "C:\\Qt\\{}\\bin".to_string(),
+        });
+        assert!(content.ends_with("    path:\n        - C:\\Qt\\{}\\bin\n"));
+    }
+}
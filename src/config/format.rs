@@ -0,0 +1,146 @@
+use serde::de::DeserializeOwned;
+use std::path::Path;
+
+use super::Config;
+use crate::context::Context;
+use crate::error::{Error, SourceFile};
+
+/// File format of the configuration, detected from the file extension
+#[derive(Debug, Clone, Copy, PartialEq, clap::ValueEnum)]
+pub enum ConfigFormat {
+    Yaml,
+    Toml,
+    Json,
+}
+
+/// Configuration file names, by order of precedence
+pub const CONFIG_FILE_NAMES: [&str; 4] = ["use.yaml", "use.yml", "use.toml", "use.json"];
+
+impl ConfigFormat {
+    /// Detect the format from the file extension, YAML being the default
+    pub fn from_path(path: &Path) -> Self {
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some(ext) if ext.eq_ignore_ascii_case("toml") => ConfigFormat::Toml,
+            Some(ext) if ext.eq_ignore_ascii_case("json") => ConfigFormat::Json,
+            _ => ConfigFormat::Yaml,
+        }
+    }
+
+    /// Deserialize the content of the configuration file
+    pub fn parse<T: DeserializeOwned>(self, source: &SourceFile) -> Result<T, Error> {
+        match self {
+            ConfigFormat::Yaml => {
+                yaml_serde::from_str(&source.content).map_err(|e| source.yaml_error(e))
+            }
+            ConfigFormat::Toml => toml::from_str(&source.content).map_err(|e| {
+                let offset = e.span().map(|span| span.start).unwrap_or_default();
+                source.error_at_offset(e.message().trim_end(), offset)
+            }),
+            ConfigFormat::Json => serde_json::from_str(&source.content).map_err(|e| {
+                let message = e.to_string();
+                let suffix = format!(" at line {} column {}", e.line(), e.column());
+                let message = message.strip_suffix(&suffix).unwrap_or(&message);
+                source.error_at(message, e.line(), e.column())
+            }),
+        }
+    }
+
+    /// Serialize a configuration in this format
+    pub fn serialize(self, value: &serde_json::Value) -> Result<String, String> {
+        match self {
+            ConfigFormat::Yaml => yaml_serde::to_string(value).map_err(|e| e.to_string()),
+            ConfigFormat::Toml => toml::to_string_pretty(value).map_err(|e| e.to_string()),
+            ConfigFormat::Json => serde_json::to_string_pretty(value)
+                .map(|json| json + "\n")
+                .map_err(|e| e.to_string()),
+        }
+    }
+}
+
+impl Config {
+    /// Convert the configuration file to another format, keeping the order of the keys
+    pub fn convert(context: &Context, to: ConfigFormat) -> Result<String, Error> {
+        let path = Path::new(&context.config_path);
+        if !path.exists() {
+            return Err(Error::ConfigNotFound(path.to_path_buf()));
+        }
+        let source = SourceFile::read(path)?;
+        let value: serde_json::Value = ConfigFormat::from_path(path).parse(&source)?;
+        to.serialize(&value)
+            .map_err(|e| source.error(&format!("Failed to convert the configuration: {}", e)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Environment;
+    use std::collections::HashMap;
+    use std::path::PathBuf;
+
+    #[test]
+    fn test_convert_formats() {
+        let yaml = r#"qt{}:
+  display: Qt {}
+  pattern:
+    path: C:\Qt
+    regex: ^(\d+\.\d+\.\d+)$
+  path:
+  - C:\Qt\{}\bin
+cmake:
+  set:
+    CMAKE_GENERATOR: Ninja
+"#;
+        let source = SourceFile {
+            path: PathBuf::from("use.yaml"),
+            content: yaml.to_string(),
+        };
+        let value: serde_json::Value = ConfigFormat::Yaml.parse(&source).unwrap();
+
+        let toml = ConfigFormat::Toml.serialize(&value).unwrap();
+        let json = ConfigFormat::Json.serialize(&value).unwrap();
+        assert!(
+            toml.starts_with("[\"qt{}\"]\ndisplay = \"Qt {}\"\n"),
+            "{}",
+            toml
+        );
+        for (content, format, name) in [
+            (toml, ConfigFormat::Toml, "use.toml"),
+            (json, ConfigFormat::Json, "use.json"),
+        ] {
+            let source = SourceFile {
+                path: PathBuf::from(name),
+                content,
+            };
+            let converted: serde_json::Value = format.parse(&source).unwrap();
+            assert_eq!(converted, value);
+        }
+        assert_eq!(ConfigFormat::Yaml.serialize(&value).unwrap(), yaml);
+    }
+
+    #[test]
+    fn test_located_errors() {
+        type Environments = HashMap<String, Environment>;
+
+        let source = SourceFile {
+            path: PathBuf::from("use.toml"),
+            content: "[qt]\ndisplay = \"Qt\"\npath = 'C:\\Qt\\bin'\n".to_string(),
+        };
+        let error = ConfigFormat::Toml
+            .parse::<Environments>(&source)
+            .unwrap_err();
+        let error = error.to_string();
+        assert!(error.starts_with("invalid type: string"), "{}", error);
+        assert!(error.contains(" --> use.toml:"), "{}", error);
+
+        let source = SourceFile {
+            path: PathBuf::from("use.json"),
+            content: "{\n  \"qt\": {\n    \"priority\": \"high\"\n  }\n}\n".to_string(),
+        };
+        let error = ConfigFormat::Json
+            .parse::<Environments>(&source)
+            .unwrap_err();
+        let error = error.to_string();
+        assert!(error.contains(" --> use.json:3:22\n"), "{}", error);
+    }
+}
@@ -0,0 +1,67 @@
+use std::io;
+use std::path::{Path, PathBuf};
+
+use super::{Config, ConfigFile, ConfigFormat};
+use crate::context::Context;
+use crate::error::{Error, SourceFile};
+
+/// Result of the migration of the legacy configuration file
+pub struct Migration {
+    pub source: PathBuf,
+    pub destination: PathBuf,
+    pub backup: PathBuf,
+    /// Description of each change made to the content by the conversion
+    pub changes: Vec<String>,
+}
+
+impl Config {
+    /// Migrate the legacy configuration file to the current location and format. The schema
+    /// didn't change, the content is only converted if the new file has another format.
+    /// The legacy file is kept as a backup, with a `.bak` extension.
+    pub fn migrate(context: &Context) -> Result<Migration, Error> {
+        let source = Context::old_config_path();
+        if !source.exists() {
+            return Err(Error::ConfigNotFound(source));
+        }
+        let destination = PathBuf::from(&context.config_path);
+        if destination.exists() {
+            return Err(Error::ConfigWrite(
+                destination,
+                io::Error::new(io::ErrorKind::AlreadyExists, "the file already exists"),
+            ));
+        }
+
+        // Check the legacy file before writing anything
+        let legacy = SourceFile::read(&source)?;
+        ConfigFormat::Yaml.parse::<ConfigFile>(&legacy)?;
+        let mut changes = Vec::new();
+        let content = match ConfigFormat::from_path(&destination) {
+            ConfigFormat::Yaml => legacy.content,
+            format => {
+                let value: serde_json::Value = ConfigFormat::Yaml.parse(&legacy)?;
+                changes.push("comments removed by the conversion to another format".to_string());
+                format
+                    .serialize(&value)
+                    .map_err(|e| Error::ConfigWrite(destination.clone(), io::Error::other(e)))?
+            }
+        };
+
+        let write_error = |path: &Path| {
+            let path = path.to_path_buf();
+            move |e| Error::ConfigWrite(path, e)
+        };
+        if let Some(parent) = destination.parent() {
+            std::fs::create_dir_all(parent).map_err(write_error(parent))?;
+        }
+        std::fs::write(&destination, content).map_err(write_error(&destination))?;
+        let backup = source.with_extension("yaml.bak");
+        std::fs::rename(&source, &backup).map_err(write_error(&source))?;
+
+        Ok(Migration {
+            source,
+            destination,
+            backup,
+            changes,
+        })
+    }
+}
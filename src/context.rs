@@ -1,4 +1,10 @@
 use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+
+use crate::config::{CONFIG_FILE_NAMES, ConfigFormat};
+
+/// Environment variable overriding the path of the configuration file
+const CONFIG_VAR: &str = "USE_CONFIG";
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum OperatingSystem {
@@ -8,6 +14,16 @@ pub enum OperatingSystem {
     Unknown,
 }
 
+impl OperatingSystem {
+    /// Separator of the list variables like PATH
+    pub fn path_separator(&self) -> &'static str {
+        match self {
+            OperatingSystem::Windows => ";",
+            _ => ":",
+        }
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, clap::ValueEnum)]
 pub enum Shell {
     #[clap(name = "cmd", alias = "clink")]
@@ -26,22 +42,36 @@ pub struct Context {
 }
 
 impl Context {
-    pub fn new() -> Self {
+    pub fn new(config: Option<PathBuf>) -> Self {
+        let config = config.or_else(|| {
+            std::env::var_os(CONFIG_VAR)
+                .filter(|path| !path.is_empty())
+                .map(PathBuf::from)
+        });
         Self {
             os: detect_os(),
             shell: detect_shell(),
-            config_path: get_config_path(),
+            config_path: get_config_path(config, &default_config_dirs()),
         }
     }
 
-    pub fn check_old_config(&self) -> bool {
-        let old_config_path = dirs::home_dir()
+    /// Path of the configuration file used before version 1.3
+    pub fn old_config_path() -> PathBuf {
+        dirs::home_dir()
             .expect("Could not find home directory")
             .join(".config")
             .join("use")
-            .join("useconfig.yaml");
-        let config_path = std::path::Path::new(&self.config_path);
-        !config_path.exists() && old_config_path.exists()
+            .join("useconfig.yaml")
+    }
+
+    pub fn check_old_config(&self) -> bool {
+        let old_config_path = Self::old_config_path();
+        let config_path = Path::new(&self.config_path);
+        // Only relevant when using the default location
+        let default_location = config_path
+            .parent()
+            .is_some_and(|dir| default_config_dirs().iter().any(|d| d == dir));
+        default_location && !config_path.exists() && old_config_path.exists()
     }
 
     pub fn check(&self, context: &str) -> bool {
@@ -66,7 +96,7 @@ impl Context {
     }
 
     pub fn create_config_file(&self) -> Result<(), std::io::Error> {
-        let config_path = std::path::Path::new(&self.config_path);
+        let config_path = Path::new(&self.config_path);
         if config_path.exists() {
             return Err(std::io::Error::other("Config file already exists"));
         }
@@ -76,14 +106,31 @@ impl Context {
             std::fs::create_dir_all(parent)?;
         }
 
-        std::fs::write(config_path, CONFIG_YAML)?;
+        let content = match ConfigFormat::from_path(config_path) {
+            ConfigFormat::Yaml => CONFIG_YAML.to_string(),
+            format => {
+                let value: serde_json::Value =
+                    yaml_serde::from_str(CONFIG_YAML).map_err(std::io::Error::other)?;
+                format.serialize(&value).map_err(std::io::Error::other)?
+            }
+        };
+        std::fs::write(config_path, content)?;
         Ok(())
     }
 
-    pub fn edit_config_file(&self) -> Result<(), std::io::Error> {
+    /// Open the configuration file in the editor, at the given line if supported
+    pub fn edit_config_file(&self, line: Option<usize>) -> Result<(), std::io::Error> {
         let editor = std::env::var("EDITOR").unwrap_or_else(|_| default_editor());
-        let status = std::process::Command::new(editor)
-            .arg(&self.config_path)
+        // The editor may contain arguments, e.g. `code --wait`, unless it's a path with spaces
+        let (program, args): (&str, Vec<&str>) = if Path::new(&editor).is_file() {
+            (&editor, Vec::new())
+        } else {
+            let mut parts = editor.split_whitespace();
+            (parts.next().unwrap_or("vim"), parts.collect())
+        };
+        let status = std::process::Command::new(program)
+            .args(args)
+            .args(editor_args(program, &self.config_path, line))
             .status()?;
         if !status.success() {
             return Err(std::io::Error::other("Failed to open editor"));
@@ -92,15 +139,39 @@ impl Context {
     }
 }
 
-fn get_config_path() -> OsString {
-    dirs::home_dir()
-        .expect("Could not find home directory")
-        .join(".config")
-        .join("use.yaml")
+/// Return the path of the configuration file, by order of precedence:
+/// 1. the path given with `--config` or `USE_CONFIG`
+/// 2. the first existing file (use.yaml, use.toml, use.json...) in the default directories
+/// 3. use.yaml in the first default directory
+fn get_config_path(config: Option<PathBuf>, config_dirs: &[PathBuf]) -> OsString {
+    if let Some(config) = config {
+        return config.into();
+    }
+    config_dirs
+        .iter()
+        .flat_map(|dir| CONFIG_FILE_NAMES.iter().map(move |name| dir.join(name)))
+        .find(|path| path.exists())
+        .unwrap_or_else(|| config_dirs[0].join(CONFIG_FILE_NAMES[0]))
         .into()
 }
 
-fn detect_os() -> OperatingSystem {
+/// Default directories of the configuration file: `$XDG_CONFIG_HOME` if set, then `~/.config`
+fn default_config_dirs() -> Vec<PathBuf> {
+    let home_config = dirs::home_dir()
+        .expect("Could not find home directory")
+        .join(".config");
+    let mut config_dirs = Vec::new();
+    if let Some(xdg_config) = std::env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .filter(|path| path.is_absolute() && *path != home_config)
+    {
+        config_dirs.push(xdg_config);
+    }
+    config_dirs.push(home_config);
+    config_dirs
+}
+
+pub fn detect_os() -> OperatingSystem {
     if cfg!(target_os = "windows") {
         OperatingSystem::Windows
     } else if cfg!(target_os = "linux") {
@@ -123,6 +194,31 @@ fn detect_shell() -> Shell {
     Shell::Unknown
 }
 
+/// Arguments to open the file at the given line, depending on the editor
+fn editor_args(program: &str, path: &OsString, line: Option<usize>) -> Vec<OsString> {
+    let Some(line) = line else {
+        return vec![path.clone()];
+    };
+    let file_name = program.rsplit(['/', '\\']).next().unwrap_or(program);
+    let name = file_name.to_lowercase();
+    let name = name.strip_suffix(".exe").unwrap_or(&name);
+    let with_line = |separator: &str| {
+        let mut arg = path.clone();
+        arg.push(format!("{}{}", separator, line));
+        arg
+    };
+    match name {
+        "code" | "code-insiders" | "codium" | "cursor" => vec!["--goto".into(), with_line(":")],
+        "subl" | "zed" | "hx" | "helix" => vec![with_line(":")],
+        "notepad++" => vec![format!("-n{}", line).into(), path.clone()],
+        "vi" | "vim" | "nvim" | "gvim" | "nano" | "emacs" | "micro" | "kak" => {
+            vec![format!("+{}", line).into(), path.clone()]
+        }
+        // Unknown editor or without support, e.g. notepad
+        _ => vec![path.clone()],
+    }
+}
+
 fn default_editor() -> String {
     if cfg!(target_os = "windows") {
         "notepad".to_string()
@@ -132,3 +228,52 @@ fn default_editor() -> String {
 }
 
 const CONFIG_YAML: &str = include_str!("config.yaml");
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn test_get_config_path() {
+        let root = std::env::temp_dir().join(format!("use-context-{}", std::process::id()));
+        let xdg = root.join("xdg");
+        let home = root.join("home");
+        fs::create_dir_all(&xdg).unwrap();
+        fs::create_dir_all(&home).unwrap();
+        let dirs = [xdg.clone(), home.clone()];
+
+        // Nothing exists, use.yaml in the first directory
+        assert_eq!(get_config_path(None, &dirs), xdg.join("use.yaml"));
+
+        // First existing file, looking in all directories
+        fs::write(home.join("use.toml"), "").unwrap();
+        assert_eq!(get_config_path(None, &dirs), home.join("use.toml"));
+        fs::write(xdg.join("use.json"), "").unwrap();
+        assert_eq!(get_config_path(None, &dirs), xdg.join("use.json"));
+
+        // Explicit path, even if it doesn't exist
+        let explicit = root.join("other.yaml");
+        assert_eq!(
+            get_config_path(Some(explicit.clone()), &dirs),
+            explicit.as_os_str()
+        );
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_editor_args() {
+        let path = OsString::from("use.yaml");
+        assert_eq!(editor_args("vim", &path, None), vec!["use.yaml"]);
+        assert_eq!(
+            editor_args("nvim", &path, Some(12)),
+            vec!["+12", "use.yaml"]
+        );
+        assert_eq!(
+            editor_args("C:\\VSCode\\Code.exe", &path, Some(12)),
+            vec!["--goto", "use.yaml:12"]
+        );
+        assert_eq!(editor_args("notepad", &path, Some(12)), vec!["use.yaml"]);
+    }
+}
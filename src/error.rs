@@ -0,0 +1,286 @@
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+/// Errors raised when reading the configuration or setting up an environment
+#[derive(Debug)]
+pub enum Error {
+    /// The configuration file doesn't exist
+    ConfigNotFound(PathBuf),
+    /// The configuration file can't be read
+    ConfigRead(PathBuf, std::io::Error),
+    /// The configuration file can't be written
+    ConfigWrite(PathBuf, std::io::Error),
+    /// The configuration file content is invalid
+    ConfigInvalid(Box<InvalidConfig>),
+    /// No environment matches the requested name
+    EnvironmentNotFound(String),
+    /// An environment with the same name already exists
+    EnvironmentExists(String),
+    /// A dependency of an environment can't be found
+    DependencyNotFound { env: String, dependency: String },
+    /// Environments which can't be used together
+    Conflict(String),
+    /// Requirements of an environment not met
+    Requirements(String),
+}
+
+/// Details of an invalid configuration, with the location of the problem if known
+#[derive(Debug)]
+pub struct InvalidConfig {
+    pub path: PathBuf,
+    pub message: String,
+    pub location: Option<Location>,
+}
+
+/// Location of an error in the configuration file, with the content of the line
+#[derive(Debug, Clone, PartialEq)]
+pub struct Location {
+    pub line: usize,
+    pub column: usize,
+    pub text: String,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::ConfigNotFound(path) => {
+                write!(f, "Config file not found at {}", path.display())
+            }
+            Error::ConfigRead(path, e) => {
+                write!(f, "Failed to read config file {}: {}", path.display(), e)
+            }
+            Error::ConfigWrite(path, e) => {
+                write!(f, "Failed to write config file {}: {}", path.display(), e)
+            }
+            Error::ConfigInvalid(invalid) => invalid.fmt(f),
+            Error::EnvironmentNotFound(name) => write!(f, "Environment {} not found", name),
+            Error::EnvironmentExists(name) => write!(f, "Environment {} already exists", name),
+            Error::DependencyNotFound { env, dependency } => write!(
+                f,
+                "Environment {} not found, required by {}",
+                dependency, env
+            ),
+            Error::Conflict(message) | Error::Requirements(message) => f.write_str(message),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+// Exit codes of the process, documented in the README.
+// 2 is used by clap for invalid command line arguments.
+/// Generic failure, not related to the configuration (e.g. unknown shell)
+pub const EXIT_FAILURE: i32 = 1;
+pub const EXIT_CONFIG_NOT_FOUND: i32 = 3;
+pub const EXIT_CONFIG_INVALID: i32 = 4;
+pub const EXIT_ENV_NOT_FOUND: i32 = 5;
+pub const EXIT_DEPENDENCY_NOT_FOUND: i32 = 6;
+pub const EXIT_CONFLICT: i32 = 7;
+pub const EXIT_REQUIREMENTS: i32 = 8;
+
+impl Error {
+    /// Exit code of the process for this error
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Error::ConfigNotFound(_) => EXIT_CONFIG_NOT_FOUND,
+            Error::ConfigWrite(_, _) | Error::EnvironmentExists(_) => EXIT_FAILURE,
+            Error::ConfigRead(_, _) | Error::ConfigInvalid(_) => EXIT_CONFIG_INVALID,
+            Error::EnvironmentNotFound(_) => EXIT_ENV_NOT_FOUND,
+            Error::DependencyNotFound { .. } => EXIT_DEPENDENCY_NOT_FOUND,
+            Error::Conflict(_) => EXIT_CONFLICT,
+            Error::Requirements(_) => EXIT_REQUIREMENTS,
+        }
+    }
+}
+
+impl fmt::Display for InvalidConfig {
+    /// Display the error with a snippet of the configuration, like:
+    /// ```text
+    /// invalid type: string "C:\bin", expected a sequence
+    ///   --> ~/.config/use.yaml:3:9
+    ///    |
+    ///  3 |   path: C:\bin
+    ///    |         ^
+    /// ```
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.message)?;
+        match &self.location {
+            Some(location) => {
+                let width = location.line.to_string().len();
+                write!(
+                    f,
+                    "\n{:width$}--> {}:{}:{}",
+                    "",
+                    self.path.display(),
+                    location.line,
+                    location.column
+                )?;
+                write!(f, "\n{:width$} |", "")?;
+                write!(f, "\n{} | {}", location.line, location.text)?;
+                write!(
+                    f,
+                    "\n{:width$} | {:column$}^",
+                    "",
+                    "",
+                    column = location.column.saturating_sub(1)
+                )
+            }
+            None => write!(f, "\n --> {}", self.path.display()),
+        }
+    }
+}
+
+/// Content of a configuration file, used to locate errors
+pub struct SourceFile {
+    pub path: PathBuf,
+    pub content: String,
+}
+
+impl SourceFile {
+    pub fn read(path: &Path) -> Result<Self, Error> {
+        let content =
+            std::fs::read_to_string(path).map_err(|e| Error::ConfigRead(path.to_path_buf(), e))?;
+        Ok(Self {
+            path: path.to_path_buf(),
+            content,
+        })
+    }
+
+    /// Create an error at the given line and column (1-based)
+    pub fn error_at(&self, message: &str, line: usize, column: usize) -> Error {
+        let location = self
+            .content
+            .lines()
+            .nth(line.saturating_sub(1))
+            .map(|text| Location {
+                line,
+                column,
+                text: text.to_string(),
+            });
+        Error::ConfigInvalid(Box::new(InvalidConfig {
+            path: self.path.clone(),
+            message: message.to_string(),
+            location,
+        }))
+    }
+
+    /// Create an error at the given byte offset in the content
+    pub fn error_at_offset(&self, message: &str, offset: usize) -> Error {
+        let before = &self.content[..offset.min(self.content.len())];
+        let line = before.matches('\n').count() + 1;
+        let column = before.len() - before.rfind('\n').map_or(0, |i| i + 1) + 1;
+        self.error_at(message, line, column)
+    }
+
+    /// Create an error without location in the file
+    pub fn error(&self, message: &str) -> Error {
+        Error::ConfigInvalid(Box::new(InvalidConfig {
+            path: self.path.clone(),
+            message: message.to_string(),
+            location: None,
+        }))
+    }
+
+    /// Create an error located at the definition of the given environment
+    pub fn error_at_key(&self, key: &str, message: &str) -> Error {
+        match self.find_key(key) {
+            Some(line) => self.error_at(message, line, 1),
+            None => self.error(message),
+        }
+    }
+
+    /// Find the line (1-based) where the given environment is defined: `key:` in YAML,
+    /// `[key]` in TOML or `"key":` in JSON. The least indented match is used.
+    pub fn find_key(&self, key: &str) -> Option<usize> {
+        let keys = [
+            key.to_string(),
+            format!("\"{}\"", key),
+            format!("'{}'", key),
+        ];
+        let is_definition = |line: &str| {
+            let trimmed = line.trim();
+            keys.iter().any(|k| {
+                trimmed == format!("[{}]", k)
+                    || line
+                        .trim_start()
+                        .strip_prefix(k.as_str())
+                        .is_some_and(|rest| rest.trim_start().starts_with(':'))
+            })
+        };
+        self.content
+            .lines()
+            .enumerate()
+            .filter(|(_, line)| is_definition(line))
+            .min_by_key(|(_, line)| line.len() - line.trim_start().len())
+            .map(|(index, _)| index + 1)
+    }
+
+    /// Convert a YAML error, removing the location from the message
+    pub fn yaml_error(&self, error: yaml_serde::Error) -> Error {
+        let message = error.to_string();
+        match error.location() {
+            Some(location) => {
+                let suffix = format!(" at line {} column {}", location.line(), location.column());
+                let message = message.strip_suffix(&suffix).unwrap_or(&message);
+                let (line, column) = self
+                    .find_value(message, location.line())
+                    .unwrap_or((location.line(), location.column()));
+                self.error_at(message, line, column)
+            }
+            None => self.error(&message),
+        }
+    }
+
+    /// Find the value quoted in the message (e.g. `invalid type: string "C:\bin"`),
+    /// searching from the given line until the end of the current block.
+    /// Properties flattened by serde are reported at the start of their mapping, this
+    /// gives a more precise location.
+    fn find_value(&self, message: &str, line: usize) -> Option<(usize, usize)> {
+        let start = message.find('"')?;
+        let end = message.rfind('"').filter(|&end| end > start)?;
+        let value = message[start + 1..end]
+            .replace("\\\\", "\\")
+            .replace("\\\"", "\"");
+        if value.is_empty() {
+            return None;
+        }
+        self.content
+            .lines()
+            .enumerate()
+            .skip(line.saturating_sub(1))
+            .take_while(|(index, text)| *index + 1 == line || text.starts_with([' ', '\t']))
+            .find_map(|(index, text)| text.find(&value).map(|column| (index + 1, column + 1)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_display_invalid_config() {
+        let source = SourceFile {
+            path: PathBuf::from("use.yaml"),
+            content: "qt:\n  path: C:\\Qt\\bin\n".to_string(),
+        };
+        let error = source.error_at("invalid type: string, expected a sequence", 2, 9);
+        assert_eq!(
+            error.to_string(),
+            "invalid type: string, expected a sequence\n \
+             --> use.yaml:2:9\n  \
+             |\n\
+             2 |   path: C:\\Qt\\bin\n  \
+             |         ^"
+        );
+
+        let error = source.error_at_key("qt", "Environment qt extends unknown environment base");
+        assert_eq!(
+            error.to_string(),
+            "Environment qt extends unknown environment base\n \
+             --> use.yaml:1:1\n  \
+             |\n\
+             1 | qt:\n  \
+             | ^"
+        );
+    }
+}
@@ -52,11 +52,22 @@ local function use_run(param)
     local use_output = io.popen(::USE:: .. " " .. param)
     local result = {}
     if use_output then
+        local lines = {}
+        for line in use_output:lines() do
+            table.insert(lines, line)
+        end
+        -- Errors are written on stderr, on failure only propagate the exit code
+        local ok, _, code = use_output:close()
+        if not ok then
+            table.insert(result, "cmd /c exit " .. (code or 1))
+            return result
+        end
+
         local filename = get_tmp_script()
         local file = io.open(filename, "w")
         if file then
             file:write("@echo off\n")
-            for line in use_output:lines() do
+            for _, line in ipairs(lines) do
                 file:write(line)
                 file:write("\n")
             end
@@ -66,11 +77,24 @@ local function use_run(param)
         else
             table.insert(result, "echo Failed to create file: " .. filename)
         end
-        use_output:close()
     end
     return result
 end
 
+--------------------------------------------------------------------------------
+-- Default environment, set up by `use` without a name
+local function use_default_env(param)
+    local output = io.popen(::USE:: .. " " .. param .. " get default-env")
+    if not output then
+        return nil
+    end
+    local env = output:read("*l")
+    output:close()
+    if env and env ~= "" then
+        return env
+    end
+end
+
 --------------------------------------------------------------------------------
 -- Filter out input to run the use executable if needed
 local function use_filter(line)
@@ -79,9 +103,30 @@ local function use_filter(line)
         return
     end
 
+    -- Skip the global options to find the command
+    local command = param
+    repeat
+        local previous = command
+        command = command:gsub('^%-%-config[ \t=]+"[^"]*"[ \t]*', "")
+        command = command:gsub("^%-%-config[ \t=]+[^ \t]+[ \t]*", "")
+        command = command:gsub("^%-%-color[ \t=]+[^ \t]+[ \t]*", "")
+    until command == previous
+
+    -- Without a name, set up the default environment if any
+    if command == "" then
+        local default_env = use_default_env(param)
+        if default_env then
+            return use_run(param .. " " .. default_env), false
+        end
+    end
+
     -- Check for any flags
-    if param == "" or param:find("^-") or param:find("^init") or param:find("^config") or param:find("^list") or param:find("^set") or param:find("^print") or param:find("^help") then
-        os.execute(::USE:: .. " " .. param)
+    if command == "" or command:find("^-") or command:find("^init") or command:find("^config") or command:find("^list") or command:find("^doctor") or command:find("^set") or command:find("^get") or command:find("^print") or command:find("^help") then
+        local ok, _, code = os.execute(::USE:: .. " " .. param)
+        if not ok then
+            -- Propagate the exit code to ERRORLEVEL
+            return "cmd /c exit " .. (code or 1), false
+        end
         return "", false
     else
         return use_run(param), false
@@ -109,5 +154,5 @@ os.setenv('USE_SHELL', 'cmd')
 -- Set current shell
 clink.argmatcher(table.unpack(string.explode(use_commands or "use")))
 :addarg(list_envs())
-:addflags("--help", "-h", "--version", "-V", "--create")
+:addflags("--help", "-h", "--version", "-V", "--create", "--config", "--color")
 :nofiles()
@@ -11,14 +11,41 @@ $null = New-Module use {
             [string[]]$Args
         )
 
-        # Handle special cases: empty args or commands that should be run directly
-        if ($Args.Count -eq 0 -or $Args[0] -in @('init', 'config', 'list', 'set', 'print', 'help') -or $Args[0] -match '^-') {
+        # Skip the global options to find the command
+        $index = 0
+        while ($index -lt $Args.Count) {
+            if ($Args[$index] -in @('--config', '--color')) {
+                $index += 2
+            } elseif ($Args[$index] -like '--config=*' -or $Args[$index] -like '--color=*') {
+                $index += 1
+            } else {
+                break
+            }
+        }
+
+        # Without a name, set up the default environment if any
+        if ($Args.Count -le $index) {
+            $default = & ::USE:: $Args get default-env
+            if ($LASTEXITCODE -ne 0 -or -not $default) {
+                & ::USE:: $Args
+                return
+            }
+            $Args = @($Args) + $default
+        }
+
+        # Handle special cases: commands that should be run directly
+        if ($Args[$index] -in @('init', 'config', 'list', 'doctor', 'set', 'get', 'print', 'help') -or $Args[$index] -match '^-') {
             & ::USE:: $Args
             return
         }
 
-        # Set an environment
-        Invoke-Expression (& ::USE:: $Args | Out-String)
+        # Set an environment, errors are written on stderr and nothing is evaluated on failure.
+        # $LASTEXITCODE keeps the exit code of use, so scripts can check it.
+        $output = & ::USE:: $Args | Out-String
+        if ($LASTEXITCODE -ne 0) {
+            return
+        }
+        Invoke-Expression $output
     }
 
     ## Completer -------------------------------------------------------
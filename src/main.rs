@@ -1,15 +1,21 @@
-use clap::Parser;
+use clap::{CommandFactory, Parser};
+use std::io::{self, IsTerminal};
+use std::path::PathBuf;
 use std::str;
 
 mod colorize;
 mod config;
 mod context;
+mod error;
 mod init;
+mod requirements;
 mod settings;
 mod shell;
-use colorize::Colorize;
+mod version;
+use colorize::{ColorMode, Colorize};
 use config::*;
 use context::*;
+use error::{EXIT_CONFIG_NOT_FOUND, EXIT_FAILURE};
 use settings::*;
 use shell::*;
 
@@ -22,6 +28,12 @@ use shell::*;
 struct Args {
     /// Name of the environment to use
     name: Option<String>,
+    /// Path of the configuration file, overrides USE_CONFIG and the default location
+    #[arg(long, global = true, value_name = "PATH")]
+    config: Option<PathBuf>,
+    /// When to use colors, overrides the color setting
+    #[arg(long, global = true, value_enum, value_name = "WHEN")]
+    color: Option<ColorMode>,
     #[clap(subcommand)]
     command: Option<Command>,
 }
@@ -39,16 +51,27 @@ enum Command {
         /// Create a new configuration file if it doesn't exist
         #[clap(long)]
         create: bool,
+        #[clap(subcommand)]
+        command: Option<ConfigCommand>,
     },
     /// List all environments
     List,
-    /// Adjust use's settings
+    /// Check the configuration against the state of the machine
+    Doctor,
+    /// Adjust use's settings, or print them all
     Set {
         /// Configuration key to edit
-        #[clap(requires = "value")]
         key: Option<SettingsKey>,
         /// Value to place into that key
         value: Option<String>,
+        /// Reset the key to its default value, or all keys if none is given
+        #[arg(long, conflicts_with = "value")]
+        reset: bool,
+    },
+    /// Print the value of a setting
+    Get {
+        /// Configuration key to print
+        key: SettingsKey,
     },
     /// Print the environment variables
     Print {
@@ -57,14 +80,84 @@ enum Command {
     },
 }
 
+#[derive(Parser)]
+enum ConfigCommand {
+    /// Print the JSON Schema of the configuration file
+    Schema,
+    /// Print the configuration file converted to another format
+    Convert {
+        /// Format of the converted configuration
+        #[arg(long, value_enum)]
+        to: ConfigFormat,
+    },
+    /// Migrate the legacy configuration file (~/.config/use/useconfig.yaml)
+    Migrate,
+    /// Add a new environment
+    Add {
+        /// Name of the environment
+        name: String,
+        /// Name displayed when using the environment, the name by default
+        #[arg(long)]
+        display: Option<String>,
+    },
+    /// Set an environment variable in an environment
+    SetVar {
+        /// Name of the environment
+        name: String,
+        /// Name of the environment variable
+        key: String,
+        /// Value of the environment variable
+        value: String,
+    },
+    /// Add a directory to the PATH of an environment
+    AddPath {
+        /// Name of the environment
+        name: String,
+        /// Directory to add
+        dir: String,
+    },
+    /// Remove an environment
+    Remove {
+        /// Name of the environment
+        name: String,
+    },
+    /// Print the effective configuration, after inheritance, shell folding, `for` filtering,
+    /// matrix and pattern expansion
+    Show {
+        /// Name of the environment, all environments by default
+        name: Option<String>,
+        /// Output format
+        #[arg(long, value_enum, default_value = "yaml")]
+        format: ConfigFormat,
+    },
+    /// Open the configuration file in the editor, at the definition of the environment
+    Edit {
+        /// Name of the environment
+        name: String,
+    },
+}
+
 fn main() {
-    let context = Context::new();
+    let mut args = Args::parse();
+    let context = Context::new(args.config.take());
     if context.os == OperatingSystem::Unknown {
-        eprintln!("{}: Unsupported operating system", "error:".error());
-        std::process::exit(1);
+        exit_with_error("Unsupported operating system", EXIT_FAILURE);
     }
-
-    let mut args = Args::parse();
+    // An invalid configuration is reported by the command itself
+    let color = args
+        .color
+        .or_else(|| Settings::color(&context))
+        .unwrap_or_default();
+    // The output of `use NAME` is evaluated by the shell, its other messages end up where
+    // stderr is. The messages echoed by the shell are only colored if stdout is a terminal.
+    let stdout_colors = colorize::use_colors(color, io::stdout().is_terminal());
+    let colors = if args.name.is_some() && args.command.is_none() {
+        colorize::use_colors(color, io::stderr().is_terminal())
+    } else {
+        stdout_colors
+    };
+    colorize::set_colors_enabled(colors);
+    colorize::set_shell_colors_enabled(stdout_colors);
 
     // Default to `list` command if no arguments are provided
     if args.name.is_none() && args.command.is_none() {
@@ -77,9 +170,11 @@ fn main() {
                 shell,
                 print_full_init,
             } => handle_init(shell, print_full_init),
-            Command::Config { create } => handle_config(&context, create),
+            Command::Config { create, command } => handle_config(&context, create, command),
             Command::List => handle_list(&context),
-            Command::Set { key, value } => handle_set(key, value),
+            Command::Doctor => handle_doctor(&context),
+            Command::Set { key, value, reset } => handle_set(&context, key, value, reset),
+            Command::Get { key } => handle_get(&context, key),
             Command::Print { name } => handle_use(&context, name, true),
         }
     } else if let Some(name) = args.name {
@@ -88,14 +183,56 @@ fn main() {
 }
 
 fn check_config(context: &Context) {
-    if context.check_old_config() {
-        eprintln!(
-            "{}: Old configuration file detected at ~/.config/use/useconfig.yaml. Please migrate to the new configuration file location {}.",
-            "error:".error(),
-            "~/.config/use.yaml".info()
+    if !context.check_old_config() {
+        return;
+    }
+
+    // Offer the migration when running interactively, the prompt is on stderr as stdout
+    // may be evaluated by the shell
+    if io::stdin().is_terminal() && io::stderr().is_terminal() {
+        eprint!(
+            "{} Old configuration file detected at {}. Migrate it to {} now? [Y/n] ",
+            "warning:".warning(),
+            Context::old_config_path().display(),
+            context.config_path.display().to_string().info()
         );
-        std::process::exit(1);
+        let mut answer = String::new();
+        if io::stdin().read_line(&mut answer).is_ok()
+            && matches!(answer.trim().to_lowercase().as_str(), "" | "y" | "yes")
+        {
+            let migration =
+                Config::migrate(context).unwrap_or_else(|e| exit_with_error(&e, e.exit_code()));
+            eprintln!("{}", migration_report(&migration));
+            return;
+        }
+    }
+    exit_with_error(
+        format!(
+            "Old configuration file detected at {}. Run {} to migrate it to {}.",
+            Context::old_config_path().display(),
+            "use config migrate".info(),
+            context.config_path.display().to_string().info()
+        ),
+        EXIT_CONFIG_NOT_FOUND,
+    );
+}
+
+fn migration_report(migration: &Migration) -> String {
+    let mut report = format!(
+        "{} migrating {} to {}",
+        "     Finished".success(),
+        migration.source.display(),
+        migration.destination.display()
+    );
+    for change in &migration.changes {
+        report += &format!("\n  {} {}", "changed:".info(), change);
     }
+    report += &format!(
+        "\n  {} the legacy file is saved as {}",
+        "backup:".info(),
+        migration.backup.display()
+    );
+    report
 }
 
 fn handle_init(shell: Shell, print_full_init: bool) {
@@ -104,13 +241,87 @@ fn handle_init(shell: Shell, print_full_init: bool) {
     } else {
         init::init_stub(shell)
     };
-    result.unwrap_or_else(|e| {
-        eprintln!("{}: {}", "error:".error(), e);
-        std::process::exit(1);
-    });
+    result.unwrap_or_else(|e| exit_with_error(e, EXIT_FAILURE));
 }
 
-fn handle_config(context: &Context, create: bool) {
+fn handle_config(context: &Context, create: bool, command: Option<ConfigCommand>) {
+    if let Some(command) = command {
+        match command {
+            ConfigCommand::Schema => println!("{}", Config::schema()),
+            ConfigCommand::Convert { to } => {
+                check_config(context);
+                let converted = Config::convert(context, to)
+                    .unwrap_or_else(|e| exit_with_error(&e, e.exit_code()));
+                print!("{}", converted);
+            }
+            ConfigCommand::Migrate => {
+                let migration =
+                    Config::migrate(context).unwrap_or_else(|e| exit_with_error(&e, e.exit_code()));
+                println!("{}", migration_report(&migration));
+            }
+            ConfigCommand::Show { name, format } => {
+                check_config(context);
+                let config =
+                    Config::new(context).unwrap_or_else(|e| exit_with_error(&e, e.exit_code()));
+                let value = config
+                    .show(name.as_deref(), &load_settings(context))
+                    .unwrap_or_else(|e| exit_with_error(&e, e.exit_code()));
+                let output = format
+                    .serialize(&value)
+                    .unwrap_or_else(|e| exit_with_error(e, EXIT_FAILURE));
+                print!("{}", output);
+            }
+            ConfigCommand::Edit { name } => {
+                check_config(context);
+                let line = Config::find_definition(context, &name)
+                    .unwrap_or_else(|e| exit_with_error(&e, e.exit_code()));
+                context
+                    .edit_config_file(Some(line))
+                    .unwrap_or_else(|e| exit_with_error(e, EXIT_FAILURE));
+            }
+            command => {
+                check_config(context);
+                let (edit, action) = match command {
+                    ConfigCommand::Add { name, display } => (
+                        ConfigEdit::Add {
+                            display: display.unwrap_or_else(|| name.clone()),
+                            name: name.clone(),
+                        },
+                        format!("added {}", name),
+                    ),
+                    ConfigCommand::SetVar { name, key, value } => (
+                        ConfigEdit::SetVar {
+                            name: name.clone(),
+                            key: key.clone(),
+                            value,
+                        },
+                        format!("set {} in {}", key, name),
+                    ),
+                    ConfigCommand::AddPath { name, dir } => (
+                        ConfigEdit::AddPath {
+                            name: name.clone(),
+                            dir: dir.clone(),
+                        },
+                        format!("added {} to the path of {}", dir, name),
+                    ),
+                    ConfigCommand::Remove { name } => (
+                        ConfigEdit::Remove { name: name.clone() },
+                        format!("removed {}", name),
+                    ),
+                    _ => unreachable!(),
+                };
+                Config::edit(context, &edit).unwrap_or_else(|e| exit_with_error(&e, e.exit_code()));
+                println!(
+                    "{} updating {}: {}",
+                    "     Finished".success(),
+                    context.config_path.display(),
+                    action
+                );
+            }
+        }
+        return;
+    }
+
     check_config(context);
     let result = if create {
         context.create_config_file().map(|_| {
@@ -121,71 +332,138 @@ fn handle_config(context: &Context, create: bool) {
             );
         })
     } else {
-        context.edit_config_file()
+        context.edit_config_file(None)
     };
-    result.unwrap_or_else(|e| {
-        eprintln!("{}: {}", "error:".error(), e);
-        std::process::exit(1);
-    });
+    result.unwrap_or_else(|e| exit_with_error(e, EXIT_FAILURE));
 }
 
 fn handle_list(context: &Context) {
     check_config(context);
-    let config = Config::new(context).unwrap_or_else(|e| {
-        eprintln!("{}: {}", "error:".error(), e);
-        std::process::exit(1);
-    });
+    let config = Config::new(context).unwrap_or_else(|e| exit_with_error(&e, e.exit_code()));
 
     config.list().iter().for_each(|env| {
         println!("{}", env);
     });
 }
 
-fn handle_set(key: Option<SettingsKey>, value: Option<String>) {
-    if let (Some(key), Some(value)) = (key, value) {
-        Settings::set(key, &value);
+fn handle_doctor(context: &Context) {
+    check_config(context);
+    let settings = load_settings(context);
+    let diagnostics =
+        Config::doctor(context, &settings).unwrap_or_else(|e| exit_with_error(&e, e.exit_code()));
+
+    let mut current_env = None;
+    for diagnostic in &diagnostics {
+        if current_env != Some(&diagnostic.env) {
+            println!("{}", diagnostic.env);
+            current_env = Some(&diagnostic.env);
+        }
+        let severity = match diagnostic.severity {
+            Severity::Info => "info:".info(),
+            Severity::Warning => "warning:".warning(),
+            Severity::Error => "error:".error(),
+        };
+        println!("  {} {}", severity, diagnostic.message);
+    }
+
+    let problems = diagnostics
+        .iter()
+        .filter(|d| d.severity > Severity::Info)
+        .count();
+    if problems == 0 {
+        println!("{} no problem found", "     Finished".success());
     } else {
-        Settings::print();
+        println!(
+            "{} {} problem(s) found",
+            "     Finished".warning(),
+            problems
+        );
+        std::process::exit(EXIT_FAILURE);
     }
 }
 
+fn handle_set(context: &Context, key: Option<SettingsKey>, value: Option<String>, reset: bool) {
+    if let Some(key) = &key
+        && (reset || value.is_some())
+        && Settings::is_overridden(context, key)
+    {
+        eprintln!(
+            "{} {} is defined in the settings of {}, which take precedence",
+            "warning:".warning(),
+            key.name(),
+            context.config_path.display()
+        );
+    }
+
+    let result = match (key, value) {
+        (key, None) if reset => Settings::reset(key),
+        (Some(key), Some(value)) => Settings::set(key, &value),
+        (None, None) => {
+            load_settings(context).print();
+            return;
+        }
+        _ => Args::command()
+            .error(
+                clap::error::ErrorKind::MissingRequiredArgument,
+                "a value is required to change a setting, or --reset to reset it",
+            )
+            .exit(),
+    };
+    result.unwrap_or_else(|e| exit_with_error(e, EXIT_FAILURE));
+}
+
+fn handle_get(context: &Context, key: SettingsKey) {
+    for value in load_settings(context).values(&key) {
+        println!("{}", value);
+    }
+}
+
+/// Load the settings, exiting on an invalid `settings` section in the configuration
+fn load_settings(context: &Context) -> Settings {
+    Settings::new(context).unwrap_or_else(|e| exit_with_error(&e, e.exit_code()))
+}
+
 fn handle_use(context: &Context, name: String, printing: bool) {
     check_config(context);
     if !printing && context.shell == Shell::Unknown {
-        eprintln!(
-            "{} Unknown shell, make sure to initialize use first (see documentation)",
-            "error:".error()
+        exit_with_error(
+            "Unknown shell, make sure to initialize use first (see documentation)",
+            EXIT_FAILURE,
         );
-        std::process::exit(1);
     }
 
+    // Errors are printed on stderr, stdout is evaluated by the shell
+    let config = Config::new(context).unwrap_or_else(|e| exit_with_error(&e, e.exit_code()));
+
+    let settings = load_settings(context);
+
     let shell_printer = if printing {
-        Box::new(DebugPrinter {}) as Box<dyn ShellPrinter>
+        Box::new(DebugPrinter {
+            prompt_var: settings.prompt_var.clone(),
+        }) as Box<dyn ShellPrinter>
     } else {
         create_shell_printer(context)
     };
 
-    let config = Config::new(context).unwrap_or_else(|e| {
-        let error = format!("{} {}", "error:".error(), e);
-        shell_printer.echo(&error);
-        std::process::exit(1);
-    });
-
-    let settings = Settings::new();
-
     config
         .print_env(&name, &settings, shell_printer.as_ref())
-        .unwrap_or_else(|e| {
-            let warning = format!("{} {}", "warning:".warning(), e);
-            shell_printer.echo(&warning);
-            std::process::exit(1);
-        });
+        .unwrap_or_else(|e| exit_with_error(&e, e.exit_code()));
+}
+
+/// Print the error on stderr and exit with the given code
+fn exit_with_error(error: impl std::fmt::Display, code: i32) -> ! {
+    eprintln!("{} {}", "error:".error(), error);
+    std::process::exit(code);
 }
 
 fn create_shell_printer(context: &Context) -> Box<dyn ShellPrinter> {
     match context.shell {
-        Shell::Powershell => Box::new(PowershellPrinter {}),
-        Shell::Cmd => Box::new(CmdPrinter {}),
+        Shell::Powershell => Box::new(PowershellPrinter {
+            os: context.os.clone(),
+        }),
+        Shell::Cmd => Box::new(CmdPrinter {
+            os: context.os.clone(),
+        }),
         Shell::Unknown => panic!("Unsupported shell"),
     }
 }
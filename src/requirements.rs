@@ -0,0 +1,313 @@
+use regex::Regex;
+use schemars::JsonSchema;
+use semver::VersionReq;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::Path;
+use std::process::Command;
+use std::sync::LazyLock;
+use which::{which, which_in};
+
+use crate::version::parse_lenient;
+
+const DEFAULT_VERSION_ARGS: [&str; 1] = ["--version"];
+
+static VERSION_REGEX: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"\d+(?:\.\d+)+(?:-[0-9A-Za-z.]+)?").unwrap());
+
+/// Requirement to check before using an environment
+#[derive(Debug, Deserialize, Serialize, JsonSchema, Clone, PartialEq)]
+#[serde(try_from = "RequirementEntry", into = "RequirementEntry")]
+pub enum Requirement {
+    /// A command available in the PATH, with an optional version constraint
+    Command {
+        name: String,
+        #[schemars(with = "Option<String>")]
+        version: Option<VersionReq>,
+        version_args: Vec<String>,
+    },
+    /// A file or directory that must exist
+    File(String),
+    /// An environment variable that must be set
+    Env(String),
+}
+
+/// A requirement is either a command (e.g. `cmake>=3.25`) or a detailed entry
+#[derive(Deserialize, Serialize, JsonSchema)]
+#[serde(untagged)]
+enum RequirementEntry {
+    Command(String),
+    Detailed {
+        #[serde(skip_serializing_if = "Option::is_none")]
+        command: Option<String>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        version: Option<String>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        version_args: Option<Vec<String>>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        file: Option<String>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        env: Option<String>,
+    },
+}
+
+impl From<Requirement> for RequirementEntry {
+    fn from(requirement: Requirement) -> Self {
+        let detailed = |command, version, version_args, file, env| RequirementEntry::Detailed {
+            command,
+            version,
+            version_args,
+            file,
+            env,
+        };
+        match requirement {
+            Requirement::Command {
+                name,
+                version,
+                version_args,
+            } if version_args == DEFAULT_VERSION_ARGS => match version {
+                Some(version) => RequirementEntry::Command(format!("{}{}", name, version)),
+                None => RequirementEntry::Command(name),
+            },
+            Requirement::Command {
+                name,
+                version,
+                version_args,
+            } => detailed(
+                Some(name),
+                version.map(|v| v.to_string()),
+                Some(version_args),
+                None,
+                None,
+            ),
+            Requirement::File(file) => detailed(None, None, None, Some(file), None),
+            Requirement::Env(env) => detailed(None, None, None, None, Some(env)),
+        }
+    }
+}
+
+impl TryFrom<RequirementEntry> for Requirement {
+    type Error = String;
+
+    fn try_from(entry: RequirementEntry) -> Result<Self, Self::Error> {
+        match entry {
+            RequirementEntry::Command(command) => Requirement::parse_command(&command, None, None),
+            RequirementEntry::Detailed {
+                command: Some(command),
+                version,
+                version_args,
+                file: None,
+                env: None,
+            } => Requirement::parse_command(&command, version.as_deref(), version_args),
+            RequirementEntry::Detailed {
+                command: None,
+                file: Some(file),
+                env: None,
+                ..
+            } => Ok(Requirement::File(file)),
+            RequirementEntry::Detailed {
+                command: None,
+                file: None,
+                env: Some(env),
+                ..
+            } => Ok(Requirement::Env(env)),
+            _ => Err("a requirement needs exactly one of command, file or env".to_string()),
+        }
+    }
+}
+
+impl Requirement {
+    /// Parse a command requirement, the version constraint being either part of the
+    /// command (e.g. `cmake>=3.25`) or given separately
+    fn parse_command(
+        command: &str,
+        version: Option<&str>,
+        version_args: Option<Vec<String>>,
+    ) -> Result<Self, String> {
+        let (name, constraint) = match command.find(['>', '<', '=', '~', '^']) {
+            Some(index) => (&command[..index], Some(&command[index..])),
+            None => (command, version),
+        };
+        let version = constraint
+            .map(|c| {
+                VersionReq::parse(c)
+                    .map_err(|e| format!("invalid version constraint '{}' for {}: {}", c, name, e))
+            })
+            .transpose()?;
+
+        Ok(Requirement::Command {
+            name: name.trim().to_string(),
+            version,
+            version_args: version_args
+                .unwrap_or_else(|| DEFAULT_VERSION_ARGS.map(String::from).to_vec()),
+        })
+    }
+
+    /// Replace the placeholders in the command, file or environment variable name
+    pub fn replace_with(&mut self, replace_fn: &dyn Fn(&str) -> String) {
+        match self {
+            Requirement::Command { name, .. } => *name = replace_fn(name),
+            Requirement::File(file) => *file = replace_fn(file),
+            Requirement::Env(env) => *env = replace_fn(env),
+        }
+    }
+
+    /// Check the requirement, returning a description of the problem if not met. The
+    /// variables set up by the dependencies are used on top of the process environment.
+    pub fn check(&self, variables: &HashMap<String, String>) -> Result<(), String> {
+        match self {
+            Requirement::Command {
+                name,
+                version,
+                version_args,
+            } => {
+                let found = match variables.get("PATH") {
+                    Some(paths) => which_in(
+                        name,
+                        Some(paths),
+                        std::env::current_dir().unwrap_or_default(),
+                    ),
+                    None => which(name),
+                };
+                let path = found.map_err(|_| format!("command {} not found", name))?;
+                let Some(req) = version else {
+                    return Ok(());
+                };
+
+                let output = Command::new(path)
+                    .args(version_args)
+                    .envs(variables)
+                    .output()
+                    .map_err(|e| format!("failed to run {}: {}", name, e))?;
+                let text = String::from_utf8_lossy(&output.stdout).to_string()
+                    + &String::from_utf8_lossy(&output.stderr);
+                let found = VERSION_REGEX
+                    .find(&text)
+                    .map(|m| m.as_str())
+                    .ok_or_else(|| format!("cannot find the version of {}", name))?;
+                match parse_lenient(found) {
+                    Some(v) if req.matches(&v) => Ok(()),
+                    _ => Err(format!("{} {} found, {} required", name, found, req)),
+                }
+            }
+            Requirement::File(file) => match Path::new(file).exists() {
+                true => Ok(()),
+                false => Err(format!("file {} not found", file)),
+            },
+            Requirement::Env(env) => {
+                match variables.contains_key(env) || std::env::var_os(env).is_some() {
+                    true => Ok(()),
+                    false => Err(format!("environment variable {} not set", env)),
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_requirements() {
+        let yaml = r#"
+- cmake>=3.25
+- ninja
+- command: python
+  version: "~3.12"
+  version_args: [-V]
+- file: Cargo.toml
+- env: PATH
+"#;
+        let requirements: Vec<Requirement> = yaml_serde::from_str(yaml).unwrap();
+        assert_eq!(
+            requirements,
+            vec![
+                Requirement::Command {
+                    name: "cmake".to_string(),
+                    version: Some(VersionReq::parse(">=3.25").unwrap()),
+                    version_args: vec!["--version".to_string()],
+                },
+                Requirement::Command {
+                    name: "ninja".to_string(),
+                    version: None,
+                    version_args: vec!["--version".to_string()],
+                },
+                Requirement::Command {
+                    name: "python".to_string(),
+                    version: Some(VersionReq::parse("~3.12").unwrap()),
+                    version_args: vec!["-V".to_string()],
+                },
+                Requirement::File("Cargo.toml".to_string()),
+                Requirement::Env("PATH".to_string()),
+            ]
+        );
+
+        let invalid: Result<Vec<Requirement>, _> =
+            yaml_serde::from_str("- file: Cargo.toml\n  env: PATH\n");
+        assert!(invalid.is_err());
+    }
+
+    #[test]
+    fn test_check_requirements() {
+        let none = HashMap::new();
+        assert!(
+            Requirement::File("Cargo.toml".to_string())
+                .check(&none)
+                .is_ok()
+        );
+        assert_eq!(
+            Requirement::File("does-not-exist.txt".to_string()).check(&none),
+            Err("file does-not-exist.txt not found".to_string())
+        );
+        assert!(Requirement::Env("PATH".to_string()).check(&none).is_ok());
+        assert_eq!(
+            Requirement::Env("USE_DOES_NOT_EXIST".to_string()).check(&none),
+            Err("environment variable USE_DOES_NOT_EXIST not set".to_string())
+        );
+        assert_eq!(
+            Requirement::parse_command("use-does-not-exist>=1.0", None, None)
+                .unwrap()
+                .check(&none),
+            Err("command use-does-not-exist not found".to_string())
+        );
+    }
+
+    #[test]
+    fn test_check_requirements_with_variables() {
+        let root = std::env::temp_dir().join(format!("use-requirements-{}", std::process::id()));
+        std::fs::create_dir_all(&root).unwrap();
+        let variables = HashMap::from([
+            (
+                "USE_TEST_HOME".to_string(),
+                root.to_string_lossy().to_string(),
+            ),
+            ("PATH".to_string(), root.to_string_lossy().to_string()),
+        ]);
+
+        assert!(
+            Requirement::Env("USE_TEST_HOME".to_string())
+                .check(&variables)
+                .is_ok()
+        );
+        assert!(
+            Requirement::Env("PATH".to_string())
+                .check(&variables)
+                .is_ok()
+        );
+
+        // Commands are looked up in the PATH set up by the dependencies
+        let command = Requirement::parse_command("use-test-tool", None, None).unwrap();
+        assert!(command.check(&variables).is_err());
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let tool = root.join("use-test-tool");
+            std::fs::write(&tool, "#!/bin/sh\necho use-test-tool 1.2.3\n").unwrap();
+            std::fs::set_permissions(&tool, std::fs::Permissions::from_mode(0o755)).unwrap();
+            assert!(command.check(&variables).is_ok());
+            assert!(command.check(&HashMap::new()).is_err());
+        }
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -1,51 +1,456 @@
+use clap::ValueEnum;
 use preferences::{AppInfo, Preferences, PreferencesMap};
+use schemars::JsonSchema;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::path::Path;
+
+use crate::colorize::{ColorMode, Colorize};
+use crate::config::{ConfigFormat, SETTINGS_KEY};
+use crate::context::Context;
+use crate::error::{Error, SourceFile};
 
 const APP_INFO: AppInfo = AppInfo {
     name: "use",
     author: "narnaud",
 };
-const UPDATE_TITLE_KEY: &str = "update-title";
+const PROVIDER_KEY_PREFIX: &str = "provider.";
+/// Placeholders available in the title template
+const TITLE_PLACEHOLDERS: [&str; 3] = ["{name}", "{display}", "{version}"];
 
 pub struct Settings {
     pub update_title: bool,
+    /// Template of the terminal title
+    pub title: String,
+    /// Environment used by `use` without a name in the shell
+    pub default_env: Option<String>,
+    pub color: ColorMode,
+    /// Environment variable set with the name of the environment, for prompts
+    pub prompt_var: String,
+    /// Preferred environment for each capability, see `provides` in the configuration
+    pub providers: HashMap<String, String>,
 }
 
 #[derive(Debug, Clone, PartialEq, clap::ValueEnum)]
 pub enum SettingsKey {
     /// Change the terminal title based on the environment chosen
     UpdateTitle,
+    /// Template of the terminal title, using {name}, {display} and {version}
+    Title,
+    /// Environment set up by `use` without a name
+    DefaultEnv,
+    /// When to use colors in the output: auto, always or never
+    Color,
+    /// Environment variable set with the name of the environment, for prompts
+    PromptVar,
+    /// Preferred environment for a capability, as `capability=environment`
+    Provider,
 }
 
-impl Settings {
-    pub fn new() -> Self {
-        let prefs = Settings::load();
+impl SettingsKey {
+    /// Name of the key, as used on the command line
+    pub fn name(&self) -> String {
+        self.to_possible_value()
+            .expect("No settings key is skipped")
+            .get_name()
+            .to_string()
+    }
+}
+
+/// Settings defined in the `settings` section of the configuration file
+#[derive(Debug, Clone, Default, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct FileSettings {
+    /// Change the terminal title based on the environment chosen
+    update_title: Option<bool>,
+    /// Template of the terminal title, using {name}, {display} and {version}
+    title: Option<String>,
+    /// Environment set up by `use` without a name
+    default_env: Option<String>,
+    /// When to use colors in the output
+    color: Option<ColorMode>,
+    /// Environment variable set with the name of the environment, for prompts
+    prompt_var: Option<String>,
+    /// Preferred environment for each capability
+    providers: Option<HashMap<String, String>>,
+}
+
+impl FileSettings {
+    /// Keys and values defined, in the format used on the command line
+    fn entries(&self) -> Vec<(SettingsKey, String)> {
+        let mut entries = Vec::new();
+        if let Some(update_title) = self.update_title {
+            entries.push((SettingsKey::UpdateTitle, update_title.to_string()));
+        }
+        if let Some(title) = &self.title {
+            entries.push((SettingsKey::Title, title.clone()));
+        }
+        if let Some(default_env) = &self.default_env {
+            entries.push((SettingsKey::DefaultEnv, default_env.clone()));
+        }
+        if let Some(color) = &self.color {
+            entries.push((SettingsKey::Color, color.to_string()));
+        }
+        if let Some(prompt_var) = &self.prompt_var {
+            entries.push((SettingsKey::PromptVar, prompt_var.clone()));
+        }
+        for (capability, env) in self.providers.iter().flatten() {
+            entries.push((SettingsKey::Provider, format!("{}={}", capability, env)));
+        }
+        entries
+    }
+
+    /// Check all values, as done by `use set`
+    pub fn validate(&self) -> Result<(), String> {
+        let mut settings = Settings::default();
+        for (key, value) in self.entries() {
+            settings.apply(&key, &value)?;
+        }
+        Ok(())
+    }
+
+    /// Read the `settings` section of the configuration file, if any
+    fn read(context: &Context) -> Result<Option<Self>, Error> {
+        #[derive(Deserialize)]
+        struct Section {
+            settings: Option<FileSettings>,
+        }
+
+        let path = Path::new(&context.config_path);
+        if !path.exists() {
+            return Ok(None);
+        }
+        let source = SourceFile::read(path)?;
+        let section: Option<Section> = ConfigFormat::from_path(path).parse(&source)?;
+        let file_settings = section.and_then(|section| section.settings);
+        if let Some(file_settings) = &file_settings {
+            file_settings
+                .validate()
+                .map_err(|e| source.error_at_key(SETTINGS_KEY, &e))?;
+        }
+        Ok(file_settings)
+    }
+}
+
+/// Check that the title template only uses known placeholders
+pub fn check_title(template: &str) -> Result<(), String> {
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        let end = rest[start..]
+            .find('}')
+            .map_or(rest.len(), |end| start + end + 1);
+        let placeholder = &rest[start..end];
+        if !TITLE_PLACEHOLDERS.contains(&placeholder) {
+            return Err(format!(
+                "Invalid placeholder '{}' in title, expected one of {}",
+                placeholder,
+                TITLE_PLACEHOLDERS.join(", ")
+            ));
+        }
+        rest = &rest[end..];
+    }
+    Ok(())
+}
+
+impl Default for Settings {
+    fn default() -> Self {
         Self {
-            update_title: prefs.get(UPDATE_TITLE_KEY).is_none_or(|s| s == "true"),
+            update_title: true,
+            title: "{display}".to_string(),
+            default_env: None,
+            color: ColorMode::Auto,
+            prompt_var: "USE_PROMPT".to_string(),
+            providers: HashMap::new(),
         }
     }
+}
+
+impl Settings {
+    /// Read the settings saved with `use set`, overridden by the `settings` section of the
+    /// configuration file
+    pub fn new(context: &Context) -> Result<Self, Error> {
+        Settings::read(context, true)
+    }
+
+    /// Color mode, read before setting up the colors. The invalid values are reported when
+    /// reading the settings for the command.
+    pub fn color(context: &Context) -> Option<ColorMode> {
+        Settings::read(context, false)
+            .ok()
+            .map(|settings| settings.color)
+    }
+
+    fn read(context: &Context, warn: bool) -> Result<Self, Error> {
+        let mut settings = Settings::default();
+        for (key, value) in Settings::load() {
+            // Invalid values can only come from a manual edit, they are reported and ignored
+            let result = match key.strip_prefix(PROVIDER_KEY_PREFIX) {
+                Some(capability) => {
+                    settings.apply(&SettingsKey::Provider, &format!("{}={}", capability, value))
+                }
+                None => {
+                    SettingsKey::from_str(&key, false).and_then(|key| settings.apply(&key, &value))
+                }
+            };
+            if let Err(e) = result
+                && warn
+            {
+                eprintln!(
+                    "{} ignoring the saved setting {} = '{}': {}",
+                    "warning:".warning(),
+                    key,
+                    value,
+                    e
+                );
+            }
+        }
+        if let Some(file_settings) = FileSettings::read(context)? {
+            for (key, value) in file_settings.entries() {
+                settings
+                    .apply(&key, &value)
+                    .expect("The settings of the configuration file are checked when read");
+            }
+        }
+        Ok(settings)
+    }
 
     fn load() -> PreferencesMap<String> {
         PreferencesMap::load(&APP_INFO, env!("CARGO_PKG_NAME")).unwrap_or_default()
     }
 
-    pub fn set(key: SettingsKey, value: &str) {
-        let mut settings = Settings::new();
+    /// Check the value and change the setting
+    fn apply(&mut self, key: &SettingsKey, value: &str) -> Result<(), String> {
+        match key {
+            SettingsKey::UpdateTitle => {
+                self.update_title = match value.to_lowercase().as_str() {
+                    "true" | "yes" | "on" | "1" => true,
+                    "false" | "no" | "off" | "0" => false,
+                    _ => {
+                        return Err(format!(
+                            "Invalid value '{}' for update-title, expected true or false",
+                            value
+                        ));
+                    }
+                }
+            }
+            SettingsKey::Title => {
+                check_title(value)?;
+                self.title = value.to_string();
+            }
+            SettingsKey::DefaultEnv => {
+                if value.trim().is_empty() {
+                    return Err(
+                        "Invalid empty default-env, use `use set --reset default-env` to remove it"
+                            .to_string(),
+                    );
+                }
+                self.default_env = Some(value.to_string());
+            }
+            SettingsKey::Color => {
+                self.color = ColorMode::from_str(value, true).map_err(|_| {
+                    format!(
+                        "Invalid value '{}' for color, expected auto, always or never",
+                        value
+                    )
+                })?
+            }
+            SettingsKey::PromptVar => {
+                let valid = value.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+                    && value.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+                if !valid {
+                    return Err(format!(
+                        "Invalid environment variable name '{}' for prompt-var",
+                        value
+                    ));
+                }
+                self.prompt_var = value.to_string();
+            }
+            SettingsKey::Provider => {
+                let (capability, env) = value
+                    .split_once('=')
+                    .filter(|(capability, _)| !capability.is_empty())
+                    .ok_or_else(|| {
+                        format!(
+                            "Invalid provider '{}', expected capability=environment",
+                            value
+                        )
+                    })?;
+                // An empty environment removes the preferred provider
+                if env.is_empty() {
+                    self.providers.remove(capability);
+                } else {
+                    self.providers
+                        .insert(capability.to_string(), env.to_string());
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Values of a setting, as displayed and stored. Unset settings have no value, and each
+    /// provider is a value
+    pub fn values(&self, key: &SettingsKey) -> Vec<String> {
+        match key {
+            SettingsKey::UpdateTitle => vec![self.update_title.to_string()],
+            SettingsKey::Title => vec![self.title.clone()],
+            SettingsKey::DefaultEnv => self.default_env.iter().cloned().collect(),
+            SettingsKey::Color => vec![self.color.to_string()],
+            SettingsKey::PromptVar => vec![self.prompt_var.clone()],
+            SettingsKey::Provider => {
+                let mut providers: Vec<_> = self
+                    .providers
+                    .iter()
+                    .map(|(capability, env)| format!("{}={}", capability, env))
+                    .collect();
+                providers.sort();
+                providers
+            }
+        }
+    }
+
+    /// Check and save a setting
+    pub fn set(key: SettingsKey, value: &str) -> Result<(), String> {
+        let mut settings = Settings::default();
+        settings.apply(&key, value)?;
+
+        let mut prefs = Settings::load();
+        match key {
+            SettingsKey::Provider => {
+                let (capability, env) = value.split_once('=').unwrap_or_default();
+                let pref_key = format!("{}{}", PROVIDER_KEY_PREFIX, capability);
+                if env.is_empty() {
+                    prefs.remove(&pref_key);
+                } else {
+                    prefs.insert(pref_key, env.to_string());
+                }
+            }
+            key => {
+                let value = settings.values(&key).remove(0);
+                prefs.insert(key.name(), value);
+            }
+        }
+        Settings::save(&prefs)
+    }
+
+    /// Reset a setting to its default value, or all settings
+    pub fn reset(key: Option<SettingsKey>) -> Result<(), String> {
+        let mut prefs = Settings::load();
         match key {
-            SettingsKey::UpdateTitle => settings.update_title = value.parse().unwrap_or(false),
+            Some(SettingsKey::Provider) => prefs.retain(|k, _| !k.starts_with(PROVIDER_KEY_PREFIX)),
+            Some(key) => {
+                prefs.remove(&key.name());
+            }
+            None => prefs.clear(),
         }
-        settings.save();
+        Settings::save(&prefs)
     }
 
-    fn save(self) {
-        let mut prefs: PreferencesMap<String> = Default::default();
-        prefs.insert(UPDATE_TITLE_KEY.into(), self.update_title.to_string());
+    fn save(prefs: &PreferencesMap<String>) -> Result<(), String> {
         prefs
             .save(&APP_INFO, env!("CARGO_PKG_NAME"))
-            .expect("Failed to save preferences");
+            .map_err(|e| format!("Failed to save the settings: {}", e))
+    }
+
+    /// Check if a setting is defined in the configuration file, which takes precedence
+    pub fn is_overridden(context: &Context, key: &SettingsKey) -> bool {
+        FileSettings::read(context)
+            .ok()
+            .flatten()
+            .is_some_and(|file_settings| {
+                file_settings
+                    .entries()
+                    .iter()
+                    .any(|(file_key, _)| file_key == key)
+            })
+    }
+
+    pub fn print(&self) {
+        for key in SettingsKey::value_variants() {
+            let values = self.values(key);
+            if values.is_empty() && *key != SettingsKey::Provider {
+                println!("{}", key.name());
+            }
+            for value in values {
+                println!("{:<15} {}", key.name(), value);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_apply_settings() {
+        let mut settings = Settings::default();
+        settings.apply(&SettingsKey::UpdateTitle, "no").unwrap();
+        assert!(!settings.update_title);
+        settings.apply(&SettingsKey::Color, "Never").unwrap();
+        assert_eq!(settings.color, ColorMode::Never);
+        settings
+            .apply(&SettingsKey::Title, "use: {display} ({name})")
+            .unwrap();
+        settings
+            .apply(&SettingsKey::PromptVar, "MY_PROMPT")
+            .unwrap();
+        settings.apply(&SettingsKey::Provider, "cxx=msvc").unwrap();
+        settings.apply(&SettingsKey::Provider, "qt=qt6").unwrap();
+        settings.apply(&SettingsKey::Provider, "qt=").unwrap();
+        assert_eq!(settings.values(&SettingsKey::Provider), vec!["cxx=msvc"]);
+        assert!(settings.values(&SettingsKey::DefaultEnv).is_empty());
+
+        for (key, value, error) in [
+            (
+                SettingsKey::UpdateTitle,
+                "maybe",
+                "Invalid value 'maybe' for update-title, expected true or false",
+            ),
+            (
+                SettingsKey::Title,
+                "{name} {path}",
+                "Invalid placeholder '{path}' in title, expected one of {name}, {display}, {version}",
+            ),
+            (
+                SettingsKey::Color,
+                "sometimes",
+                "Invalid value 'sometimes' for color, expected auto, always or never",
+            ),
+            (
+                SettingsKey::PromptVar,
+                "1PROMPT",
+                "Invalid environment variable name '1PROMPT' for prompt-var",
+            ),
+            (
+                SettingsKey::Provider,
+                "msvc",
+                "Invalid provider 'msvc', expected capability=environment",
+            ),
+        ] {
+            assert_eq!(settings.apply(&key, value).unwrap_err(), error);
+        }
+        assert_eq!(
+            settings.values(&SettingsKey::Title),
+            vec!["use: {display} ({name})"]
+        );
     }
 
-    pub fn print() {
-        let settings = Settings::new();
-        println!("update-title    {}", settings.update_title);
+    #[test]
+    fn test_file_settings() {
+        let yaml = "update_title: false\ncolor: never\nproviders:\n  cxx: msvc\n";
+        let file_settings: FileSettings = yaml_serde::from_str(yaml).unwrap();
+        assert!(file_settings.validate().is_ok());
+        assert_eq!(
+            file_settings.entries(),
+            vec![
+                (SettingsKey::UpdateTitle, "false".to_string()),
+                (SettingsKey::Color, "never".to_string()),
+                (SettingsKey::Provider, "cxx=msvc".to_string()),
+            ]
+        );
+
+        let file_settings: FileSettings = yaml_serde::from_str("prompt_var: my-prompt").unwrap();
+        assert!(file_settings.validate().is_err());
+        assert!(yaml_serde::from_str::<FileSettings>("unknown: true").is_err());
     }
 }
@@ -1,42 +1,265 @@
-use crate::shell::ShellPrinter;
+use crate::context::OperatingSystem;
+use crate::shell::{ShellPrinter, Token, tokenize};
 
-pub struct CmdPrinter {}
+pub struct CmdPrinter {
+    /// Operating system, giving the separator of the list variables
+    pub os: OperatingSystem,
+}
+
+impl CmdPrinter {
+    fn append_line(&self, key: &str, value: &str, separator: Option<&str>) -> String {
+        let separator = separator.unwrap_or(self.os.path_separator());
+        format!(
+            "@set {}=%{}%{}{}",
+            escape(key),
+            key,
+            escape(separator),
+            escape_value(value)
+        )
+    }
+
+    fn prepend_line(&self, key: &str, value: &str, separator: Option<&str>) -> String {
+        let separator = separator.unwrap_or(self.os.path_separator());
+        format!(
+            "@set {}={}{}%{}%",
+            escape(key),
+            escape_value(value),
+            escape(separator),
+            key
+        )
+    }
+
+    /// Lines setting the list variable, with the separator only if the variable is defined
+    fn paths_lines(
+        &self,
+        key: &str,
+        paths: &[String],
+        separator: Option<&str>,
+        prepend: bool,
+    ) -> String {
+        let separator = separator.unwrap_or(self.os.path_separator());
+        let value = paths
+            .iter()
+            .map(|path| escape_value(path))
+            .collect::<Vec<_>>()
+            .join(&escape(separator));
+        let combined = if prepend {
+            format!("{}{}%{}%", value, escape(separator), key)
+        } else {
+            format!("%{}%{}{}", key, escape(separator), value)
+        };
+        format!(
+            "@if defined {} set {}={}\n@if not defined {} set {}={}",
+            escape(key),
+            escape(key),
+            combined,
+            escape(key),
+            escape(key),
+            value
+        )
+    }
+}
 
 impl ShellPrinter for CmdPrinter {
     fn echo(&self, message: &str) {
-        println!("echo {}", message);
+        println!("echo {}", escape(message));
     }
 
     fn set(&self, key: &str, value: &str) {
-        println!("@set {}={}", key, value);
+        println!("@set {}={}", escape(key), escape_value(value));
     }
 
-    fn append(&self, key: &str, value: &str) {
-        println!("@set {}=%{}%;{}", key, key, value);
+    fn append(&self, key: &str, value: &str, separator: Option<&str>) {
+        println!("{}", self.append_line(key, value, separator));
     }
 
-    fn prepend(&self, key: &str, value: &str) {
-        println!("@set {}={};%{}%", key, value, key);
+    fn prepend(&self, key: &str, value: &str, separator: Option<&str>) {
+        println!("{}", self.prepend_line(key, value, separator));
     }
 
-    fn prepend_path(&self, path: &str) {
-        println!("@set PATH={};%PATH%", path);
+    fn prepend_path(&self, path: &str, separator: Option<&str>) {
+        println!("{}", self.prepend_line("PATH", path, separator));
+    }
+
+    fn prepend_paths(&self, key: &str, paths: &[String], separator: Option<&str>) {
+        println!("{}", self.paths_lines(key, paths, separator, true));
+    }
+
+    fn append_paths(&self, key: &str, paths: &[String], separator: Option<&str>) {
+        println!("{}", self.paths_lines(key, paths, separator, false));
     }
 
     fn go(&self, path: &str) {
         // Use chdir, as may be aliased to something else
-        println!("chdir /D {}", path);
+        println!("chdir /D {}", escape_value(path));
     }
 
     fn change_title(&self, title: &str) {
-        println!("TITLE {}", title);
+        println!("TITLE {}", escape(title));
     }
 
     fn alias(&self, name: &str, command: &str) {
-        println!("doskey {}= \"{}\" $*", name, command);
+        println!(
+            "doskey {}= \"{}\" $*",
+            escape(name),
+            doskey_command(command)
+        );
     }
 
     fn env_variable(&self, env: &str) -> String {
         format!("%{}%", env)
     }
 }
+
+/// Escape a literal text. The output is run as a batch file: `%` is doubled, and the other
+/// special characters are escaped with a caret, including `"` so it doesn't start a quoted
+/// section where carets are kept.
+fn escape(text: &str) -> String {
+    let mut result = String::new();
+    for c in text.chars() {
+        match c {
+            '^' | '&' | '|' | '<' | '>' | '(' | ')' | '"' => {
+                result.push('^');
+                result.push(c);
+            }
+            '%' => result.push_str("%%"),
+            // A command can't span several lines
+            '\r' | '\n' => result.push(' '),
+            _ => result.push(c),
+        }
+    }
+    result
+}
+
+/// Escape a configuration value, the `${VAR}` references becoming `%VAR%`
+fn escape_value(value: &str) -> String {
+    tokenize(value)
+        .into_iter()
+        .map(|token| match token {
+            Token::Text(text) => escape(text),
+            Token::Var(name) => format!("%{}%", name),
+        })
+        .collect()
+}
+
+/// Escape the command of an alias, written between quotes in the doskey macro: `$` is doubled
+/// for doskey and `%` for the batch file. Quotes are removed, they can't be part of a path.
+fn doskey_command(command: &str) -> String {
+    tokenize(command)
+        .into_iter()
+        .map(|token| match token {
+            Token::Text(text) => text.replace('"', "").replace('$', "$$").replace('%', "%%"),
+            Token::Var(name) => format!("%{}%", name),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_escape_values() {
+        for (value, expected) in [
+            ("", ""),
+            ("simple", "simple"),
+            ("with space ", "with space "),
+            ("a&b", "a^&b"),
+            ("a|b", "a^|b"),
+            ("a^b", "a^^b"),
+            ("<in>out", "^<in^>out"),
+            ("C:\\Program Files (x86)\\", "C:\\Program Files ^(x86^)\\"),
+            ("100%", "100%%"),
+            ("%PATH%", "%%PATH%%"),
+            ("\"quoted & safe\"", "^\"quoted ^& safe^\""),
+            ("\"&\"&", "^\"^&^\"^&"),
+            ("it's", "it's"),
+            ("$HOME `x` !x!", "$HOME `x` !x!"),
+            ("a;b,c=d", "a;b,c=d"),
+            ("{}[]*?#@~", "{}[]*?#@~"),
+            ("line\r\nbreak", "line  break"),
+            ("${1A}", "${1A}"),
+            ("${QTDIR}", "%QTDIR%"),
+            ("${QTDIR}\\bin", "%QTDIR%\\bin"),
+            ("${A}${B}", "%A%%B%"),
+            ("50% of ${A} & more", "50%% of %A% ^& more"),
+        ] {
+            assert_eq!(escape_value(value), expected, "{}", value);
+        }
+    }
+
+    #[test]
+    fn test_escape_messages() {
+        for (message, expected) in [
+            ("Qt 6.8", "Qt 6.8"),
+            ("Tools (x64) | ${A}", "Tools ^(x64^) ^| ${A}"),
+        ] {
+            assert_eq!(escape(message), expected);
+        }
+    }
+
+    #[test]
+    fn test_list_separators() {
+        let windows = CmdPrinter {
+            os: OperatingSystem::Windows,
+        };
+        assert_eq!(
+            windows.prepend_line("PATH", "C:\\Program Files (x86)\\x", None),
+            "@set PATH=C:\\Program Files ^(x86^)\\x;%PATH%"
+        );
+        assert_eq!(
+            windows.append_line("INCLUDE", "${QTDIR}\\include", None),
+            "@set INCLUDE=%INCLUDE%;%QTDIR%\\include"
+        );
+        assert_eq!(
+            windows.append_line("FLAGS", "/O2", Some(" & ")),
+            "@set FLAGS=%FLAGS% ^& /O2"
+        );
+    }
+
+    #[test]
+    fn test_path_lists() {
+        let windows = CmdPrinter {
+            os: OperatingSystem::Windows,
+        };
+        let paths = vec![
+            "C:\\Qt\\plugins".to_string(),
+            "${QTDIR}\\plugins".to_string(),
+        ];
+        assert_eq!(
+            windows.paths_lines("QT_PLUGIN_PATH", &paths, None, true),
+            "@if defined QT_PLUGIN_PATH set \
+             QT_PLUGIN_PATH=C:\\Qt\\plugins;%QTDIR%\\plugins;%QT_PLUGIN_PATH%\n\
+             @if not defined QT_PLUGIN_PATH set QT_PLUGIN_PATH=C:\\Qt\\plugins;%QTDIR%\\plugins"
+        );
+        let paths = vec!["C:\\Program Files (x86)\\lib".to_string()];
+        assert_eq!(
+            windows.paths_lines("LIB", &paths, None, false),
+            "@if defined LIB set LIB=%LIB%;C:\\Program Files ^(x86^)\\lib\n\
+             @if not defined LIB set LIB=C:\\Program Files ^(x86^)\\lib"
+        );
+    }
+
+    #[test]
+    fn test_escape_keys_and_aliases() {
+        for (key, expected) in [
+            ("PATH", "PATH"),
+            ("ProgramFiles(x86)", "ProgramFiles^(x86^)"),
+            ("A&B", "A^&B"),
+        ] {
+            assert_eq!(escape(key), expected);
+        }
+        for (command, expected) in [
+            ("C:\\Tools\\tool.exe", "C:\\Tools\\tool.exe"),
+            (
+                "C:\\Program Files (x86)\\a&b.exe",
+                "C:\\Program Files (x86)\\a&b.exe",
+            ),
+            ("\"C:\\Program Files\\x.exe\"", "C:\\Program Files\\x.exe"),
+            ("C:\\$Tools\\%x%.exe", "C:\\$$Tools\\%%x%%.exe"),
+            ("${QTDIR}\\bin\\designer.exe", "%QTDIR%\\bin\\designer.exe"),
+        ] {
+            assert_eq!(doskey_command(command), expected);
+        }
+    }
+}
@@ -1,8 +1,25 @@
 use crate::colorize::Colorize;
-use crate::shell::ShellPrinter;
+use crate::config::ACTIVE_ENVS_VAR;
+use crate::shell::{ShellPrinter, Token, tokenize};
 use console::style;
 
-pub struct DebugPrinter {}
+pub struct DebugPrinter {
+    /// Environment variable set for the prompt, not printed
+    pub prompt_var: String,
+}
+
+impl DebugPrinter {
+    /// Render a configuration value, highlighting the `${VAR}` references
+    fn value(&self, value: &str) -> String {
+        tokenize(value)
+            .into_iter()
+            .map(|token| match token {
+                Token::Text(text) => text.to_string(),
+                Token::Var(name) => self.env_variable(name),
+            })
+            .collect()
+    }
+}
 
 impl ShellPrinter for DebugPrinter {
     fn start(&self, name: &str, env_name: &str) {
@@ -35,31 +52,43 @@ impl ShellPrinter for DebugPrinter {
     }
 
     fn set(&self, key: &str, value: &str) {
-        if key == "USE_PROMPT" {
-            // Skip printing USE_PROMPT to avoid clutter
+        if key == self.prompt_var || key == ACTIVE_ENVS_VAR {
+            // Skip printing the prompt variable and the active environments to avoid clutter
             return;
         }
-        println!("{} {} = {}", style('│').green(), key, value);
+        println!("{} {} = {}", style('│').green(), key, self.value(value));
+    }
+
+    fn append(&self, key: &str, value: &str, _separator: Option<&str>) {
+        println!("{} {} += {}", style('│').green(), key, self.value(value));
     }
 
-    fn append(&self, key: &str, value: &str) {
-        println!("{} {} += {}", style('│').green(), key, value);
+    fn prepend(&self, key: &str, value: &str, _separator: Option<&str>) {
+        println!("{} {} += {}", style('│').green(), key, self.value(value));
     }
 
-    fn prepend(&self, key: &str, value: &str) {
-        println!("{} {} += {}", style('│').green(), key, value);
+    fn prepend_path(&self, path: &str, _separator: Option<&str>) {
+        println!("{} PATH += {}", style('│').green(), self.value(path));
     }
 
-    fn prepend_path(&self, path: &str) {
-        println!("{} PATH += {}", style('│').green(), path);
+    fn prepend_paths(&self, key: &str, paths: &[String], _separator: Option<&str>) {
+        for path in paths {
+            println!("{} {} += {}", style('│').green(), key, self.value(path));
+        }
+    }
+
+    fn append_paths(&self, key: &str, paths: &[String], _separator: Option<&str>) {
+        for path in paths {
+            println!("{} {} += {}", style('│').green(), key, self.value(path));
+        }
     }
 
     fn go(&self, path: &str) {
-        println!("{} {}", style("└→").green(), path);
+        println!("{} {}", style("└→").green(), self.value(path));
     }
 
     fn alias(&self, name: &str, command: &str) {
-        println!("{} {} → {}", style('│').green(), name, command);
+        println!("{} {} → {}", style('│').green(), name, self.value(command));
     }
 
     fn change_title(&self, _title: &str) {
@@ -1,9 +1,49 @@
-use crate::colorize::Colorize;
+use crate::colorize::{Colorize, for_shell};
+use regex::Regex;
 use std::str;
+use std::sync::LazyLock;
 
+/// Reference to an environment variable in a configuration value, e.g. `${QTDIR}`
+pub static ENV_VAR_REGEX: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"\$\{([A-Za-z_][A-Za-z0-9_]*)\}").unwrap());
+
+/// Part of a configuration value
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Token<'a> {
+    /// Literal text, to escape for the shell
+    Text(&'a str),
+    /// Name of an environment variable referenced with `${NAME}`
+    Var(&'a str),
+}
+
+/// Split a configuration value into literal text and environment variable references
+pub fn tokenize(value: &str) -> Vec<Token<'_>> {
+    let mut tokens = Vec::new();
+    let mut last = 0;
+    for caps in ENV_VAR_REGEX.captures_iter(value) {
+        let reference = caps.get(0).unwrap();
+        if reference.start() > last {
+            tokens.push(Token::Text(&value[last..reference.start()]));
+        }
+        tokens.push(Token::Var(caps.get(1).unwrap().as_str()));
+        last = reference.end();
+    }
+    if last < value.len() {
+        tokens.push(Token::Text(&value[last..]));
+    }
+    tokens
+}
+
+/// Print the shell commands setting up an environment. Values, paths and alias commands are
+/// given as written in the configuration, each printer escaping them for its shell and
+/// replacing the `${VAR}` references.
 pub trait ShellPrinter {
     fn start(&self, _name: &str, env_name: &str) {
-        let text = format!("{} {}", " Configuring".success(), env_name);
+        let text = format!(
+            "{} {}",
+            for_shell(" Configuring", |t| t.success()),
+            env_name
+        );
         self.echo(&text);
     }
     fn finish(&self) {
@@ -13,8 +53,8 @@ pub trait ShellPrinter {
     fn finalize(&self, _name: &str, env_name: &str) {
         let text = format!(
             "{} setting up {}",
-            "    Finished".success(),
-            env_name.info()
+            for_shell("    Finished", |t| t.success()),
+            for_shell(env_name, |t| t.info())
         );
         self.echo(&text);
     }
@@ -25,15 +65,26 @@ pub trait ShellPrinter {
 
     fn echo(&self, message: &str);
     fn set(&self, key: &str, value: &str);
-    fn append(&self, key: &str, value: &str);
-    fn prepend(&self, key: &str, value: &str);
-    fn prepend_path(&self, path: &str);
+    /// Append a value to a list variable, using the separator if given, or the one of the
+    /// platform
+    fn append(&self, key: &str, value: &str, separator: Option<&str>);
+    /// Prepend a value to a list variable, using the separator if given, or the one of the
+    /// platform
+    fn prepend(&self, key: &str, value: &str, separator: Option<&str>);
+    fn prepend_path(&self, path: &str, separator: Option<&str>);
+    /// Prepend paths to a list variable, in the given order, without a trailing separator if
+    /// the variable is empty
+    fn prepend_paths(&self, key: &str, paths: &[String], separator: Option<&str>);
+    /// Append paths to a list variable, in the given order, without a leading separator if
+    /// the variable is empty
+    fn append_paths(&self, key: &str, paths: &[String], separator: Option<&str>);
     fn go(&self, path: &str);
     fn alias(&self, name: &str, command: &str);
 
     fn change_title(&self, title: &str);
 
-    /// Return the shell-specific syntax for referencing an environment variable name.
+    /// Return the shell-specific syntax for referencing an environment variable name, used
+    /// in the raw scripts.
     /// Example: "PATH" -> "%PATH%" for cmd, "$env:PATH" for PowerShell
     fn env_variable(&self, env: &str) -> String;
 }
@@ -44,3 +95,63 @@ mod powershell;
 pub use cmd::CmdPrinter;
 pub use debug::DebugPrinter;
 pub use powershell::PowershellPrinter;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Printer recording the echoed messages
+    #[derive(Default)]
+    struct EchoPrinter {
+        messages: std::cell::RefCell<Vec<String>>,
+    }
+
+    impl ShellPrinter for EchoPrinter {
+        fn echo(&self, message: &str) {
+            self.messages.borrow_mut().push(message.to_string());
+        }
+        fn set(&self, _key: &str, _value: &str) {}
+        fn append(&self, _key: &str, _value: &str, _separator: Option<&str>) {}
+        fn prepend(&self, _key: &str, _value: &str, _separator: Option<&str>) {}
+        fn prepend_path(&self, _path: &str, _separator: Option<&str>) {}
+        fn prepend_paths(&self, _key: &str, _paths: &[String], _separator: Option<&str>) {}
+        fn append_paths(&self, _key: &str, _paths: &[String], _separator: Option<&str>) {}
+        fn go(&self, _path: &str) {}
+        fn alias(&self, _name: &str, _command: &str) {}
+        fn change_title(&self, _title: &str) {}
+        fn env_variable(&self, env: &str) -> String {
+            env.to_string()
+        }
+    }
+
+    #[test]
+    fn test_messages_without_shell_colors() {
+        crate::colorize::set_shell_colors_enabled(false);
+        let printer = EchoPrinter::default();
+        printer.start("qt", "Qt 6.8");
+        printer.finalize("qt", "Qt 6.8");
+        crate::colorize::set_shell_colors_enabled(true);
+        assert_eq!(
+            printer.messages.take(),
+            vec![" Configuring Qt 6.8", "    Finished setting up Qt 6.8"]
+        );
+    }
+
+    #[test]
+    fn test_tokenize() {
+        assert_eq!(tokenize(""), vec![]);
+        assert_eq!(tokenize("C:\\Qt"), vec![Token::Text("C:\\Qt")]);
+        assert_eq!(
+            tokenize("${QTDIR}\\bin;${PATH}"),
+            vec![
+                Token::Var("QTDIR"),
+                Token::Text("\\bin;"),
+                Token::Var("PATH")
+            ]
+        );
+        assert_eq!(
+            tokenize("$QTDIR ${1A} ${A-B} ${_A}"),
+            vec![Token::Text("$QTDIR ${1A} ${A-B} "), Token::Var("_A")]
+        );
+    }
+}
@@ -1,41 +1,323 @@
-use crate::shell::ShellPrinter;
+use crate::context::OperatingSystem;
+use crate::shell::{ShellPrinter, Token, tokenize};
 
-pub struct PowershellPrinter {}
+pub struct PowershellPrinter {
+    /// Operating system PowerShell is running on, giving the separator of the list variables
+    pub os: OperatingSystem,
+}
+
+impl PowershellPrinter {
+    fn append_line(&self, key: &str, value: &str, separator: Option<&str>) -> String {
+        let separator = separator.unwrap_or(self.os.path_separator());
+        format!(
+            "{} += {}",
+            env_var(key),
+            quote(&format!("{}{}", separator, value))
+        )
+    }
+
+    fn prepend_line(&self, key: &str, value: &str, separator: Option<&str>) -> String {
+        let separator = separator.unwrap_or(self.os.path_separator());
+        let var = env_var(key);
+        format!(
+            "{} = {} + {}",
+            var,
+            quote(&format!("{}{}", value, separator)),
+            var
+        )
+    }
+
+    fn prepend_paths_line(&self, key: &str, paths: &[String], separator: Option<&str>) -> String {
+        let separator = separator.unwrap_or(self.os.path_separator());
+        let var = env_var(key);
+        let value = paths.join(separator);
+        format!(
+            "{} = if ({}) {{ {} + {} }} else {{ {} }}",
+            var,
+            var,
+            quote(&format!("{}{}", value, separator)),
+            var,
+            quote(&value)
+        )
+    }
+
+    fn append_paths_line(&self, key: &str, paths: &[String], separator: Option<&str>) -> String {
+        let separator = separator.unwrap_or(self.os.path_separator());
+        let var = env_var(key);
+        let value = paths.join(separator);
+        format!(
+            "{} = if ({}) {{ {} + {} }} else {{ {} }}",
+            var,
+            var,
+            var,
+            quote(&format!("{}{}", separator, value)),
+            quote(&value)
+        )
+    }
+}
 
 impl ShellPrinter for PowershellPrinter {
     fn echo(&self, message: &str) {
-        println!("Write-Host '{}'", message);
+        println!("Write-Host {}", single_quote(message));
     }
 
     fn set(&self, key: &str, value: &str) {
-        println!("$env:{} = '{}'", key, value);
+        println!("{} = {}", env_var(key), quote(value));
+    }
+
+    fn append(&self, key: &str, value: &str, separator: Option<&str>) {
+        println!("{}", self.append_line(key, value, separator));
     }
 
-    fn append(&self, key: &str, value: &str) {
-        println!("$env:{} += \";{}\"", key, value);
+    fn prepend(&self, key: &str, value: &str, separator: Option<&str>) {
+        println!("{}", self.prepend_line(key, value, separator));
     }
 
-    fn prepend(&self, key: &str, value: &str) {
-        println!("$env:{} = \"{};$env:{}\"", key, value, key);
+    fn prepend_path(&self, path: &str, separator: Option<&str>) {
+        println!("{}", self.prepend_line("PATH", path, separator));
     }
 
-    fn prepend_path(&self, path: &str) {
-        println!("$env:PATH = \"{};$env:PATH\"", path);
+    fn prepend_paths(&self, key: &str, paths: &[String], separator: Option<&str>) {
+        println!("{}", self.prepend_paths_line(key, paths, separator));
+    }
+
+    fn append_paths(&self, key: &str, paths: &[String], separator: Option<&str>) {
+        println!("{}", self.append_paths_line(key, paths, separator));
     }
 
     fn go(&self, path: &str) {
-        println!("Set-Location {}", path);
+        println!("Set-Location -LiteralPath {}", quote(path));
     }
 
     fn change_title(&self, title: &str) {
-        println!("$host.ui.RawUI.WindowTitle = '{}'", title);
+        println!("$host.ui.RawUI.WindowTitle = {}", single_quote(title));
     }
 
     fn alias(&self, name: &str, command: &str) {
-        println!("function {} {{ & \"{}\" @args }}", name, command);
+        println!("{} = {{ & {} @args }}", function(name), quote(command));
     }
 
     fn env_variable(&self, env: &str) -> String {
         format!("$env:{}", env)
     }
 }
+
+/// Quotation marks ending a single-quoted string, escaped by doubling them
+const SINGLE_QUOTES: [char; 5] = ['\'', '\u{2018}', '\u{2019}', '\u{201A}', '\u{201B}'];
+/// Characters with a special meaning in a double-quoted string, escaped with a backtick
+const DOUBLE_QUOTED_SPECIALS: [char; 6] = ['`', '$', '"', '\u{201C}', '\u{201D}', '\u{201E}'];
+
+/// Literal string in single quotes, where nothing is expanded
+fn single_quote(text: &str) -> String {
+    let mut result = String::from('\'');
+    for c in text.chars() {
+        if SINGLE_QUOTES.contains(&c) {
+            result.push(c);
+        }
+        result.push(c);
+    }
+    result.push('\'');
+    result
+}
+
+/// String for a configuration value: in single quotes if it is only literal text, in double
+/// quotes otherwise, so the `${VAR}` references are expanded and nothing else
+fn quote(value: &str) -> String {
+    let tokens = tokenize(value);
+    if !tokens.iter().any(|token| matches!(token, Token::Var(_))) {
+        return single_quote(value);
+    }
+
+    let mut result = String::from('"');
+    for token in tokens {
+        match token {
+            Token::Text(text) => {
+                for c in text.chars() {
+                    if DOUBLE_QUOTED_SPECIALS.contains(&c) {
+                        result.push('`');
+                    }
+                    result.push(c);
+                }
+            }
+            // Braces delimit the name from the text following it
+            Token::Var(name) => result += &format!("${{env:{}}}", name),
+        }
+    }
+    result.push('"');
+    result
+}
+
+/// Escape a name inside the braces of a variable (`${...}`)
+fn braced_name(name: &str) -> String {
+    let mut result = String::new();
+    for c in name.chars() {
+        if matches!(c, '`' | '{' | '}') {
+            result.push('`');
+        }
+        result.push(c);
+    }
+    result
+}
+
+/// Environment variable, using braces if the name has special characters
+fn env_var(key: &str) -> String {
+    if !key.is_empty() && key.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+        format!("$env:{}", key)
+    } else {
+        format!("${{env:{}}}", braced_name(key))
+    }
+}
+
+/// Variable of the function drive, defining the function when assigned a script block
+fn function(name: &str) -> String {
+    format!("${{function:{}}}", braced_name(name))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_quote_values() {
+        for (value, expected) in [
+            ("", "''"),
+            ("simple", "'simple'"),
+            ("with space", "'with space'"),
+            ("it's", "'it''s'"),
+            ("it\u{2019}s", "'it\u{2019}\u{2019}s'"),
+            (
+                "\u{2018}x\u{201A}\u{201B}",
+                "'\u{2018}\u{2018}x\u{201A}\u{201A}\u{201B}\u{201B}'",
+            ),
+            ("\"double\"", "'\"double\"'"),
+            ("$HOME", "'$HOME'"),
+            ("$(Get-Date)", "'$(Get-Date)'"),
+            ("`n", "'`n'"),
+            ("a;b|c&d>e<f", "'a;b|c&d>e<f'"),
+            ("%PATH%", "'%PATH%'"),
+            ("^(x)!", "'^(x)!'"),
+            ("{}[]*?#@~,=", "'{}[]*?#@~,='"),
+            ("tab\tand\nline", "'tab\tand\nline'"),
+            ("C:\\Program Files (x86)\\", "'C:\\Program Files (x86)\\'"),
+            ("${1A}", "'${1A}'"),
+            ("${QTDIR}", "\"${env:QTDIR}\""),
+            ("${QTDIR}\\bin", "\"${env:QTDIR}\\bin\""),
+            ("${A}_suffix", "\"${env:A}_suffix\""),
+            ("${A}${B}", "\"${env:A}${env:B}\""),
+            ("it's ${A}", "\"it's ${env:A}\""),
+            ("$ ${A} `", "\"`$ ${env:A} ``\""),
+            ("\"${A}\"", "\"`\"${env:A}`\"\""),
+            (
+                "\u{201C}${A}\u{201D}\u{201E}",
+                "\"`\u{201C}${env:A}`\u{201D}`\u{201E}\"",
+            ),
+            ("$(rm x) ${A}", "\"`$(rm x) ${env:A}\""),
+        ] {
+            assert_eq!(quote(value), expected, "{}", value);
+        }
+    }
+
+    #[test]
+    fn test_quote_messages() {
+        for (message, expected) in [("Qt 6.8", "'Qt 6.8'"), ("Bob's ${A}", "'Bob''s ${A}'")] {
+            assert_eq!(single_quote(message), expected);
+        }
+    }
+
+    #[test]
+    fn test_list_separators() {
+        let linux = PowershellPrinter {
+            os: OperatingSystem::Linux,
+        };
+        for (line, expected) in [
+            (
+                linux.prepend_line("PATH", "/opt/tools/bin", None),
+                "$env:PATH = '/opt/tools/bin:' + $env:PATH",
+            ),
+            (
+                linux.append_line("LD_LIBRARY_PATH", "${QTDIR}/lib", None),
+                "$env:LD_LIBRARY_PATH += \":${env:QTDIR}/lib\"",
+            ),
+            (
+                linux.append_line("FLAGS", "-O2", Some(" ")),
+                "$env:FLAGS += ' -O2'",
+            ),
+        ] {
+            assert_eq!(line, expected);
+        }
+
+        let macos = PowershellPrinter {
+            os: OperatingSystem::MacOS,
+        };
+        assert_eq!(
+            macos.append_line("PKG_CONFIG_PATH", "/opt/lib/pkgconfig", None),
+            "$env:PKG_CONFIG_PATH += ':/opt/lib/pkgconfig'"
+        );
+
+        let windows = PowershellPrinter {
+            os: OperatingSystem::Windows,
+        };
+        assert_eq!(
+            windows.prepend_line("PATH", "C:\\Tools", None),
+            "$env:PATH = 'C:\\Tools;' + $env:PATH"
+        );
+        assert_eq!(
+            windows.prepend_line("TAGS", "it's", Some(",")),
+            "$env:TAGS = 'it''s,' + $env:TAGS"
+        );
+    }
+
+    #[test]
+    fn test_path_lists() {
+        let linux = PowershellPrinter {
+            os: OperatingSystem::Linux,
+        };
+        let paths = vec!["/opt/lib".to_string(), "${QTDIR}/lib".to_string()];
+        assert_eq!(
+            linux.prepend_paths_line("LD_LIBRARY_PATH", &paths, None),
+            "$env:LD_LIBRARY_PATH = if ($env:LD_LIBRARY_PATH) \
+             { \"/opt/lib:${env:QTDIR}/lib:\" + $env:LD_LIBRARY_PATH } \
+             else { \"/opt/lib:${env:QTDIR}/lib\" }"
+        );
+        assert_eq!(
+            linux.append_paths_line("PYTHONPATH", &paths[..1], None),
+            "$env:PYTHONPATH = if ($env:PYTHONPATH) \
+             { $env:PYTHONPATH + ':/opt/lib' } else { '/opt/lib' }"
+        );
+
+        let windows = PowershellPrinter {
+            os: OperatingSystem::Windows,
+        };
+        let paths = vec!["C:\\Qt\\plugins".to_string(), "C:\\My Plugins".to_string()];
+        assert_eq!(
+            windows.prepend_paths_line("QT_PLUGIN_PATH", &paths, None),
+            "$env:QT_PLUGIN_PATH = if ($env:QT_PLUGIN_PATH) \
+             { 'C:\\Qt\\plugins;C:\\My Plugins;' + $env:QT_PLUGIN_PATH } \
+             else { 'C:\\Qt\\plugins;C:\\My Plugins' }"
+        );
+    }
+
+    #[test]
+    fn test_keys_and_aliases() {
+        for (key, expected) in [
+            ("PATH", "$env:PATH"),
+            ("_Var1", "$env:_Var1"),
+            ("ProgramFiles(x86)", "${env:ProgramFiles(x86)}"),
+            ("A-B.C", "${env:A-B.C}"),
+            ("A B", "${env:A B}"),
+            ("{x}", "${env:`{x`}}"),
+            ("a`b", "${env:a``b}"),
+        ] {
+            assert_eq!(env_var(key), expected);
+        }
+        for (name, expected) in [
+            ("ll", "${function:ll}"),
+            ("git-st", "${function:git-st}"),
+            ("py3.12", "${function:py3.12}"),
+            ("a b", "${function:a b}"),
+            ("x}y", "${function:x`}y}"),
+        ] {
+            assert_eq!(function(name), expected);
+        }
+    }
+}
@@ -0,0 +1,212 @@
+use schemars::JsonSchema;
+use semver::{BuildMetadata, Prerelease, Version, VersionReq};
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+
+/// Strategy used to order the environments created by a pattern
+#[derive(Debug, Deserialize, Serialize, JsonSchema, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum VersionSort {
+    /// Lenient semantic versioning, invalid versions are last using natural ordering
+    #[default]
+    Semver,
+    /// Numbers inside the version are compared numerically
+    Natural,
+    /// Plain string comparison
+    Lexical,
+    /// Modification time of the matched file or directory
+    Mtime,
+}
+
+impl VersionSort {
+    /// Compare two versions, the newest one first
+    pub fn compare(self, a: &str, b: &str) -> Ordering {
+        match self {
+            VersionSort::Semver => match (parse_lenient(a), parse_lenient(b)) {
+                (Some(v_a), Some(v_b)) => v_b.cmp(&v_a),
+                // Valid versions always come before the invalid ones
+                (Some(_), None) => Ordering::Less,
+                (None, Some(_)) => Ordering::Greater,
+                (None, None) => natural_cmp(b, a),
+            },
+            VersionSort::Natural => natural_cmp(b, a),
+            VersionSort::Lexical => b.cmp(a),
+            // Modification times are not part of the version, keep the natural order as a fallback
+            VersionSort::Mtime => natural_cmp(b, a),
+        }
+    }
+}
+
+/// Version selected when a partial name matches several environments of a pattern
+#[derive(Debug, Deserialize, Serialize, JsonSchema, Clone, PartialEq, Default)]
+#[serde(try_from = "String", into = "String")]
+pub enum Prefer {
+    #[default]
+    Newest,
+    Oldest,
+    /// Newest version matching the constraint (e.g. `~6.8`, `>=6.5, <6.9`)
+    Constraint(#[schemars(with = "String")] VersionReq),
+}
+
+impl TryFrom<String> for Prefer {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        match value.as_str() {
+            "newest" => Ok(Prefer::Newest),
+            "oldest" => Ok(Prefer::Oldest),
+            constraint => VersionReq::parse(constraint)
+                .map(Prefer::Constraint)
+                .map_err(|e| format!("invalid prefer value '{}': {}", constraint, e)),
+        }
+    }
+}
+
+impl From<Prefer> for String {
+    fn from(prefer: Prefer) -> Self {
+        match prefer {
+            Prefer::Newest => "newest".to_string(),
+            Prefer::Oldest => "oldest".to_string(),
+            Prefer::Constraint(req) => req.to_string(),
+        }
+    }
+}
+
+/// Parse a version, accepting missing minor/patch components, a leading `v`
+/// and prerelease suffixes not separated by a dash (e.g. `6.8`, `v2022.3`, `1.0rc1`)
+pub fn parse_lenient(version: &str) -> Option<Version> {
+    if let Ok(v) = Version::parse(version) {
+        return Some(v);
+    }
+
+    let version = version.trim().trim_start_matches(['v', 'V']);
+    let end = version
+        .find(|c: char| !c.is_ascii_digit() && c != '.')
+        .unwrap_or(version.len());
+    let (numbers, suffix) = version.split_at(end);
+
+    let mut components = Vec::new();
+    for component in numbers.trim_end_matches('.').split('.') {
+        components.push(component.parse::<u64>().ok()?);
+    }
+    let mut result = Version::new(
+        components[0],
+        components.get(1).copied().unwrap_or(0),
+        components.get(2).copied().unwrap_or(0),
+    );
+
+    // Additional components (e.g. 1.2.3.4) are kept as build metadata
+    if components.len() > 3 {
+        let build = components[3..]
+            .iter()
+            .map(|c| c.to_string())
+            .collect::<Vec<_>>()
+            .join(".");
+        result.build = BuildMetadata::new(&build).ok()?;
+    }
+
+    let suffix = suffix.trim_start_matches(['-', '_', '.', '+']);
+    if !suffix.is_empty() {
+        let pre = suffix
+            .split(|c: char| !c.is_ascii_alphanumeric())
+            .filter(|s| !s.is_empty())
+            .collect::<Vec<_>>()
+            .join(".");
+        result.pre = Prerelease::new(&pre).ok()?;
+    }
+
+    Some(result)
+}
+
+/// Compare two strings, handling sequences of digits as numbers
+pub fn natural_cmp(a: &str, b: &str) -> Ordering {
+    let mut a_chars = a.chars().peekable();
+    let mut b_chars = b.chars().peekable();
+
+    loop {
+        match (a_chars.peek(), b_chars.peek()) {
+            (None, None) => return Ordering::Equal,
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(ca), Some(cb)) if ca.is_ascii_digit() && cb.is_ascii_digit() => {
+                let mut num_a = String::new();
+                while let Some(c) = a_chars.next_if(|c| c.is_ascii_digit()) {
+                    num_a.push(c);
+                }
+                let mut num_b = String::new();
+                while let Some(c) = b_chars.next_if(|c| c.is_ascii_digit()) {
+                    num_b.push(c);
+                }
+                let trimmed_a = num_a.trim_start_matches('0');
+                let trimmed_b = num_b.trim_start_matches('0');
+                let ordering = trimmed_a
+                    .len()
+                    .cmp(&trimmed_b.len())
+                    .then_with(|| trimmed_a.cmp(trimmed_b));
+                if ordering != Ordering::Equal {
+                    return ordering;
+                }
+            }
+            (Some(ca), Some(cb)) => {
+                let ordering = ca.cmp(cb);
+                if ordering != Ordering::Equal {
+                    return ordering;
+                }
+                a_chars.next();
+                b_chars.next();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_lenient() {
+        assert_eq!(parse_lenient("6.8.2"), Some(Version::new(6, 8, 2)));
+        assert_eq!(parse_lenient("6.10"), Some(Version::new(6, 10, 0)));
+        assert_eq!(parse_lenient("v2022.3"), Some(Version::new(2022, 3, 0)));
+        assert_eq!(parse_lenient("17"), Some(Version::new(17, 0, 0)));
+        assert_eq!(
+            parse_lenient("6.9-beta1"),
+            Some(Version::parse("6.9.0-beta1").unwrap())
+        );
+        assert_eq!(
+            parse_lenient("1.0rc1"),
+            Some(Version::parse("1.0.0-rc1").unwrap())
+        );
+        assert_eq!(
+            parse_lenient("1.2.3.4"),
+            Some(Version::parse("1.2.3+4").unwrap())
+        );
+        assert_eq!(parse_lenient("r26b"), None);
+        assert_eq!(parse_lenient("current"), None);
+    }
+
+    #[test]
+    fn test_compare_versions() {
+        fn sort<'a>(mode: VersionSort, versions: &[&'a str]) -> Vec<&'a str> {
+            let mut versions = versions.to_vec();
+            versions.sort_by(|a, b| mode.compare(a, b));
+            versions
+        }
+
+        assert_eq!(
+            sort(
+                VersionSort::Semver,
+                &["6.9", "6.10", "6.10-beta1", "5.15.2"]
+            ),
+            vec!["6.10", "6.10-beta1", "6.9", "5.15.2"]
+        );
+        assert_eq!(
+            sort(VersionSort::Natural, &["r9", "r26b", "r26", "r10"]),
+            vec!["r26b", "r26", "r10", "r9"]
+        );
+        assert_eq!(
+            sort(VersionSort::Lexical, &["6.9", "6.10", "5.15"]),
+            vec!["6.9", "6.10", "5.15"]
+        );
+    }
+}